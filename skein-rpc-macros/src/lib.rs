@@ -0,0 +1,505 @@
+//! Derives typed clients and server dispatch from a trait describing an RPC
+//! service. See the service attribute for details.
+
+use std::collections::HashMap;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span,TokenStream as TokenStream2};
+use quote::{format_ident,quote};
+use syn::{
+    parse_macro_input,
+    parse_quote,
+    spanned::Spanned,
+    Error,
+    FnArg,
+    GenericArgument,
+    Ident,
+    ItemTrait,
+    LitStr,
+    Pat,
+    PathArguments,
+    ReturnType,
+    TraitItem,
+    TraitItemFn,
+    Type
+};
+
+/// Defines an RPC service from a trait of async methods, each of which takes
+/// `&self` or `&mut self` followed by arguments that are both Serialize and
+/// Deserialize, and returns a `Result` or `AsyncResult` whose value is
+/// likewise.
+///
+/// For a trait `Calculator` this generates:
+///
+/// * `CalculatorServer<T>`, a Responder wrapping any implementation, which
+///   binds positional or named params to the method's arguments by name and
+///   describes the methods in response to rpc.discover. With skein-rpc's
+///   schema feature, params and results are given the JSON Schema of their
+///   types where these implement JsonSchema.
+/// * `CalculatorClient<C>`, wrapping any Client with a typed method for each
+///   of those in the trait, which sends the arguments as named params.
+///
+/// Methods are called by their Rust name unless renamed with
+/// `#[rpc(name = "...")]`, which must leave each name distinct, and the
+/// first line of any doc comment becomes the method's summary. Arguments of
+/// type Option may be omitted by callers. An argument of type
+/// `RequestContext` or `&RequestContext` is given the request's context
+/// instead, and isn't part of the params or client.
+///
+/// The trait is made Send + Sync and passed through async_trait, so
+/// implementations must be annotated with `#[skein_rpc::async_trait]`.
+#[proc_macro_attribute]
+pub fn service(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return Error::new(Span::call_site(), "service takes no arguments").to_compile_error().into();
+    }
+
+    let item = parse_macro_input!(item as ItemTrait);
+
+    match expand(item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into()
+    }
+}
+
+struct Method {
+    ident: Ident,
+    name: String,
+    summary: Option<String>,
+    args: Vec<(Ident,Type)>,
+    call: Vec<TokenStream2>,
+    result: Type,
+    params: Ident
+}
+
+fn expand(mut item: ItemTrait) -> Result<TokenStream2, Error> {
+    if !item.generics.params.is_empty() {
+        return Err(Error::new(item.generics.span(), "service traits can't be generic"));
+    }
+
+    let mut methods = Vec::new();
+
+    for trait_item in item.items.iter_mut() {
+        if let TraitItem::Fn(function) = trait_item {
+            methods.push(method(&item.ident, function)?);
+        }
+    }
+
+    check_distinct(&methods)?;
+
+    item.supertraits.push(parse_quote!(::core::marker::Send));
+    item.supertraits.push(parse_quote!(::core::marker::Sync));
+
+    let vis = &item.vis;
+    let title = item.ident.to_string();
+    let server = format_ident!("{}Server", item.ident);
+    let client = format_ident!("{}Client", item.ident);
+    let service = &item.ident;
+
+    let params_structs = methods.iter().map(|method| {
+        let params = &method.params;
+        let fields = method.args.iter().map(|(ident, ty)| quote!(#ident: #ty));
+
+        quote! {
+            #[doc(hidden)]
+            #[derive(::skein_rpc::__private::serde::Serialize,::skein_rpc::__private::serde::Deserialize)]
+            #[serde(crate = "::skein_rpc::__private::serde")]
+            #vis struct #params {
+                #(#fields),*
+            }
+        }
+    });
+
+    let descriptions = methods.iter().map(|method| {
+        let name = &method.name;
+        let result = schema(&method.result);
+
+        let summary = method.summary.as_ref().map(|summary| quote!(.with_summary(#summary)));
+        let params = method.args.iter().map(|(ident, ty)| {
+            let param = param(ident, ty);
+            let schema = schema(ty);
+
+            quote!(.with_param(::skein_rpc::openrpc::ContentDescriptor::from(&#param).with_schema(#schema)))
+        });
+
+        quote! {
+            ::skein_rpc::openrpc::Method::new(#name)
+                #summary
+                #(#params)*
+                .with_result(::skein_rpc::openrpc::ContentDescriptor::new("result", #result))
+        }
+    });
+
+    let arms = methods.iter().map(|method| {
+        let name = &method.name;
+        let ident = &method.ident;
+        let params = &method.params;
+        let declared = declared_params(method);
+        let args = &method.call;
+
+        quote! {
+            #name => {
+                let params : #params = request.bind_params(&#declared)?;
+                let result = self.0.#ident(#(#args),*).await?;
+
+                Ok(::skein_rpc::__private::serde_json::to_value(result)?)
+            },
+        }
+    });
+
+    let calls = methods.iter().map(|method| {
+        let name = &method.name;
+        let ident = &method.ident;
+        let params = &method.params;
+        let result = &method.result;
+        let inputs = method.args.iter().map(|(ident, ty)| quote!(#ident: #ty));
+        let args = method.args.iter().map(|(ident, _)| ident);
+
+        // Methods without arguments send no params at all.
+        let sent = if method.args.is_empty() {
+            quote!(())
+        }
+        else {
+            quote!(#params { #(#args),* })
+        };
+
+        let doc = format!("Calls {} on the service.", name);
+
+        quote! {
+            #[doc = #doc]
+            pub async fn #ident(&self, #(#inputs),*) -> ::skein_rpc::AsyncResult<#result> {
+                ::skein_rpc::Client::rpc_call(&self.client, #name, #sent).await
+            }
+        }
+    });
+
+    let server_doc = format!("Serves an implementation of {} as a Responder.", title);
+    let client_doc = format!("Calls the methods of {} through any Client.", title);
+
+    Ok(quote! {
+        #[::skein_rpc::__private::async_trait]
+        #item
+
+        #(#params_structs)*
+
+        #[doc = #server_doc]
+        #vis struct #server<T>(pub T);
+
+        impl<T> #server<T> where T : #service + 'static {
+            pub fn new(service: T) -> Self {
+                Self(service)
+            }
+
+            pub fn service(&self) -> &T {
+                &self.0
+            }
+
+            pub fn into_inner(self) -> T {
+                self.0
+            }
+        }
+
+        #[::skein_rpc::__private::async_trait]
+        impl<T> ::skein_rpc::Responder for #server<T> where T : #service + 'static {
+            fn describe(&self) -> ::core::option::Option<::skein_rpc::openrpc::Document> {
+                Some(
+                    ::skein_rpc::openrpc::Document::new(#title, env!("CARGO_PKG_VERSION"))
+                        #(.with_method(#descriptions))*
+                )
+            }
+
+            async fn respond(&mut self, request: &::skein_rpc::rpc::Request) -> ::skein_rpc::AsyncResult<::skein_rpc::__private::serde_json::Value> {
+                match request.method().as_str() {
+                    #(#arms)*
+                    method => {
+                        Err(::std::boxed::Box::new(::skein_rpc::rpc::ErrorResponse::new(
+                            ::skein_rpc::rpc::ErrorCode::MethodNotFound,
+                            ::skein_rpc::rpc::ErrorCode::MethodNotFound.message(),
+                            Some(::skein_rpc::__private::serde_json::json!({ "method": method }))
+                        )))
+                    }
+                }
+            }
+        }
+
+        #[doc = #client_doc]
+        #[derive(Clone,Debug)]
+        #vis struct #client<C> {
+            client: C
+        }
+
+        impl<C> #client<C> where C : ::skein_rpc::Client + Sync {
+            pub fn new(client: C) -> Self {
+                Self { client }
+            }
+
+            pub fn client(&self) -> &C {
+                &self.client
+            }
+
+            pub fn into_inner(self) -> C {
+                self.client
+            }
+
+            #(#calls)*
+        }
+    })
+}
+
+// Validates a trait method, strips its rpc attribute, and collects what's
+// needed to generate its dispatch and client call.
+fn method(service: &Ident, function: &mut TraitItemFn) -> Result<Method, Error> {
+    let signature = &function.sig;
+
+    if signature.asyncness.is_none() {
+        return Err(Error::new(signature.fn_token.span(), "service methods must be async"));
+    }
+
+    if !signature.generics.params.is_empty() {
+        return Err(Error::new(signature.generics.span(), "service methods can't be generic"));
+    }
+
+    let mut inputs = signature.inputs.iter();
+
+    match inputs.next() {
+        Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() => { },
+        _ => {
+            return Err(Error::new(signature.span(), "service methods must take &self or &mut self"));
+        }
+    }
+
+    let mut args = Vec::new();
+    let mut call = Vec::new();
+
+    for input in inputs {
+        match input {
+            FnArg::Typed(arg) => {
+                match arg.pat.as_ref() {
+                    Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => {
+                        let ident = &pat.ident;
+
+                        // The request's context is passed along rather than
+                        // being one of the params.
+                        match context_type(arg.ty.as_ref()) {
+                            Some(true) => call.push(quote!(request.context())),
+                            Some(false) => call.push(quote!(request.context().clone())),
+                            None => {
+                                call.push(quote!(params.#ident));
+                                args.push((ident.clone(), arg.ty.as_ref().clone()));
+                            }
+                        }
+                    },
+                    pat => {
+                        return Err(Error::new(pat.span(), "service method arguments must be plain identifiers"));
+                    }
+                }
+            },
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new(receiver.span(), "unexpected receiver"));
+            }
+        }
+    }
+
+    let result = match &signature.output {
+        ReturnType::Type(_, ty) => ok_type(ty),
+        ReturnType::Default => None
+    };
+
+    let result = result.ok_or_else(|| Error::new(signature.output.span(), "service methods must return a Result"))?;
+
+    let mut name = signature.ident.to_string();
+    let mut summary = None;
+
+    for attr in &function.attrs {
+        if attr.path().is_ident("rpc") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+
+                    Ok(())
+                }
+                else {
+                    Err(meta.error("unsupported rpc attribute"))
+                }
+            })?;
+        }
+        else if attr.path().is_ident("doc") && summary.is_none() {
+            if let syn::Meta::NameValue(syn::MetaNameValue { value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(doc), .. }), .. }) = &attr.meta {
+                let doc = doc.value().trim().to_string();
+
+                if !doc.is_empty() {
+                    summary = Some(doc);
+                }
+            }
+        }
+    }
+
+    function.attrs.retain(|attr| !attr.path().is_ident("rpc"));
+
+    let params = format_ident!("__{}{}Params", service, pascal_case(&signature.ident.to_string()));
+
+    Ok(Method {
+        ident: signature.ident.clone(),
+        name,
+        summary,
+        args,
+        call,
+        result,
+        params
+    })
+}
+
+// Rejects methods that would share an RPC name, and so be unreachable, or
+// share a params struct, as `a_b` and `a__b` would.
+fn check_distinct(methods: &[Method]) -> Result<(), Error> {
+    let mut names = HashMap::new();
+    let mut params = HashMap::new();
+
+    for method in methods {
+        if let Some(other) = names.insert(method.name.as_str(), &method.ident) {
+            return Err(Error::new(method.ident.span(), format!("method name {:?} is already used by `{}`", method.name, other)));
+        }
+
+        if let Some(other) = params.insert(method.params.to_string(), &method.ident) {
+            return Err(Error::new(method.ident.span(), format!("params struct `{}` is already generated for `{}`", method.params, other)));
+        }
+    }
+
+    Ok(())
+}
+
+// The declared Params for a method, where Option arguments are optional.
+fn declared_params(method: &Method) -> TokenStream2 {
+    let params = method.args.iter().map(|(ident, ty)| param(ident, ty));
+
+    quote!(::skein_rpc::Params::new(vec![ #(#params),* ]))
+}
+
+// The declared Param for an argument, optional if it is an Option.
+fn param(ident: &Ident, ty: &Type) -> TokenStream2 {
+    let name = ident.to_string();
+
+    if is_option(ty) {
+        quote!(::skein_rpc::Param::optional(#name))
+    }
+    else {
+        quote!(::skein_rpc::Param::required(#name))
+    }
+}
+
+// The JSON Schema of a type if it implements JsonSchema and skein-rpc has the
+// schema feature enabled, otherwise an unconstrained schema.
+fn schema(ty: &Type) -> TokenStream2 {
+    quote! {
+        {
+            #[allow(unused_imports)]
+            use ::skein_rpc::openrpc::{DescribedSchema as _, UndescribedSchema as _};
+
+            (&::skein_rpc::openrpc::SchemaProbe::<#ty>::new()).schema()
+        }
+    }
+}
+
+// Extracts T from a return type such as Result<T, E> or AsyncResult<T>.
+fn ok_type(ty: &Type) -> Option<Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None
+    };
+
+    if segment.ident != "Result" && segment.ident != "AsyncResult" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => {
+            match args.args.first()? {
+                GenericArgument::Type(ty) => Some(ty.clone()),
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+// Returns whether the type is a RequestContext, Some(true) if by reference.
+fn context_type(ty: &Type) -> Option<bool> {
+    let (ty, by_ref) = match ty {
+        Type::Reference(reference) => (reference.elem.as_ref(), true),
+        ty => (ty, false)
+    };
+
+    match ty {
+        Type::Path(path) if path.path.segments.last()?.ident == "RequestContext" => Some(by_ref),
+        _ => None
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.path.segments.last().map(|s| s.ident == "Option").unwrap_or(false),
+        _ => false
+    }
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_').map(|part| {
+        let mut chars = part.chars();
+
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new()
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expand_error(item: ItemTrait) -> String {
+        expand(item).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_result_types() {
+        let item : ItemTrait = parse_quote! {
+            trait Example {
+                async fn a(&self) -> Result<i64, Error>;
+                async fn b(&self) -> skein_rpc::AsyncResult<i64>;
+            }
+        };
+
+        assert!(expand(item).is_ok());
+
+        let item : ItemTrait = parse_quote! {
+            trait Example {
+                async fn a(&self) -> LookupResult<i64>;
+            }
+        };
+
+        assert_eq!(expand_error(item), "service methods must return a Result");
+    }
+
+    #[test]
+    fn test_distinct() {
+        let item : ItemTrait = parse_quote! {
+            trait Example {
+                async fn a_b(&self) -> AsyncResult<()>;
+                async fn a__b(&self) -> AsyncResult<()>;
+            }
+        };
+
+        assert_eq!(expand_error(item), "params struct `__ExampleABParams` is already generated for `a_b`");
+
+        let item : ItemTrait = parse_quote! {
+            trait Example {
+                async fn a(&self) -> AsyncResult<()>;
+                #[rpc(name = "a")]
+                async fn b(&self) -> AsyncResult<()>;
+            }
+        };
+
+        assert_eq!(expand_error(item), "method name \"a\" is already used by `a`");
+    }
+}
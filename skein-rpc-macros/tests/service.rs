@@ -0,0 +1,151 @@
+use std::sync::atomic::{AtomicI64,Ordering};
+
+use async_trait::async_trait;
+use serde_json::{json,Value};
+use tokio::sync::Mutex;
+
+use skein_rpc::AsyncResult;
+use skein_rpc::Client;
+use skein_rpc::RequestContext;
+use skein_rpc::Responder;
+use skein_rpc::rpc;
+
+#[skein_rpc::service]
+pub trait Calculator {
+    /// Adds two numbers together
+    async fn add(&self, a: i64, b: i64) -> AsyncResult<i64>;
+
+    #[rpc(name = "calculator.greet")]
+    async fn greet(&self, name: String, title: Option<String>) -> AsyncResult<String>;
+
+    async fn bump(&mut self) -> AsyncResult<i64>;
+
+    async fn fail(&self) -> Result<(), rpc::ErrorResponse>;
+
+    async fn queue(&self, context: &RequestContext, suffix: String) -> AsyncResult<Option<String>>;
+}
+
+#[derive(Default)]
+struct Adder {
+    bumps: AtomicI64
+}
+
+#[skein_rpc::async_trait]
+impl Calculator for Adder {
+    async fn add(&self, a: i64, b: i64) -> AsyncResult<i64> {
+        Ok(a + b)
+    }
+
+    async fn greet(&self, name: String, title: Option<String>) -> AsyncResult<String> {
+        match title {
+            Some(title) => Ok(format!("Hello, {} {}", title, name)),
+            None => Ok(format!("Hello, {}", name))
+        }
+    }
+
+    async fn bump(&mut self) -> AsyncResult<i64> {
+        Ok(self.bumps.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    async fn fail(&self) -> Result<(), rpc::ErrorResponse> {
+        Err(rpc::ErrorResponse::new(rpc::ErrorCode::ServerError(-32001), "Failed", None))
+    }
+
+    async fn queue(&self, context: &RequestContext, suffix: String) -> AsyncResult<Option<String>> {
+        Ok(context.queue.as_ref().map(|queue| format!("{}{}", queue, suffix)))
+    }
+}
+
+// Sends requests straight to a responder, round-tripping them through JSON as
+// a transport would.
+struct Loopback<R> {
+    responder: Mutex<R>
+}
+
+#[async_trait]
+impl<R> Client for Loopback<R> where R : Responder {
+    async fn rpc_request(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<Value> {
+        let request : rpc::Request = serde_json::from_value(serde_json::to_value(rpc::Request::new(1, method, params))?)?;
+
+        let response = match self.responder.lock().await.respond(&request).await {
+            Ok(result) => rpc::Response::result_for(&request, result),
+            Err(err) => rpc::Response::error_from(&request, err)
+        };
+
+        let response : rpc::Response = serde_json::from_value(serde_json::to_value(response)?)?;
+
+        match response.error() {
+            Some(error) => Err(Box::new(error.clone())),
+            None => Ok(response.result().cloned().unwrap_or(Value::Null))
+        }
+    }
+}
+
+fn client() -> CalculatorClient<Loopback<CalculatorServer<Adder>>> {
+    CalculatorClient::new(Loopback { responder: Mutex::new(CalculatorServer::new(Adder::default())) })
+}
+
+#[tokio::test]
+async fn test_client() {
+    let client = client();
+
+    assert_eq!(client.add(2, 3).await.unwrap(), 5);
+    assert_eq!(client.greet("Ada".to_string(), None).await.unwrap(), "Hello, Ada");
+    assert_eq!(client.greet("Ada".to_string(), Some("Dr.".to_string())).await.unwrap(), "Hello, Dr. Ada");
+    assert_eq!(client.bump().await.unwrap(), 1);
+    assert_eq!(client.bump().await.unwrap(), 2);
+    assert_eq!(client.queue("a".to_string()).await.unwrap(), None);
+
+    let err = client.fail().await.unwrap_err();
+
+    assert_eq!(err.downcast_ref::<rpc::ErrorResponse>().unwrap().code(), -32001);
+}
+
+#[tokio::test]
+async fn test_dispatch() {
+    let mut server = CalculatorServer::new(Adder::default());
+
+    // Positional and named params are both bound by argument name.
+    assert_eq!(server.respond(&rpc::Request::new(1, "add", Some(json!([ 1, 2 ])))).await.unwrap(), json!(3));
+    assert_eq!(server.respond(&rpc::Request::new(2, "add", Some(json!({ "b": 4, "a": 5 })))).await.unwrap(), json!(9));
+    assert_eq!(server.respond(&rpc::Request::new(3, "calculator.greet", Some(json!([ "Ada" ])))).await.unwrap(), json!("Hello, Ada"));
+
+    let context = RequestContext { queue: Some("calculator".to_string()), ..RequestContext::default() };
+    let request = rpc::Request::new(4, "queue", Some(json!([ ".control" ]))).with_context(context);
+
+    assert_eq!(server.respond(&request).await.unwrap(), json!("calculator.control"));
+
+    let request = rpc::Request::new(4, "add", Some(json!([ 1 ])));
+    let response = rpc::Response::error_from(&request, server.respond(&request).await.unwrap_err());
+
+    assert!(response.error().unwrap().is_invalid_params());
+
+    let request = rpc::Request::new(5, "greet", None);
+    let response = rpc::Response::error_from(&request, server.respond(&request).await.unwrap_err());
+
+    assert!(response.error().unwrap().is_method_not_found());
+}
+
+#[test]
+fn test_describe() {
+    let document = CalculatorServer::new(Adder::default()).describe().unwrap();
+
+    assert_eq!(
+        document.methods.iter().map(|method| method.name.as_str()).collect::<Vec<_>>(),
+        vec![ "add", "calculator.greet", "bump", "fail", "queue" ]
+    );
+
+    let add = document.method("add").unwrap();
+
+    assert_eq!(add.summary.as_deref(), Some("Adds two numbers together"));
+    assert_eq!(add.params.len(), 2);
+    assert_eq!(add.params[0].schema["type"], "integer");
+    assert_eq!(add.result.as_ref().unwrap().schema["type"], "integer");
+
+    let greet = document.method("calculator.greet").unwrap();
+
+    assert!(greet.params[0].required);
+    assert!(!greet.params[1].required);
+    assert_eq!(greet.params[1].schema["type"], json!([ "string", "null" ]));
+    assert_eq!(greet.result.as_ref().unwrap().schema["type"], "string");
+}
@@ -1,7 +1,10 @@
-use futures::future::FutureExt;
-use futures::stream::StreamExt;
-use std::collections::HashMap;
-use std::convert::TryFrom;
+use futures::future::{FutureExt,try_join_all};
+use futures::stream::{self,Stream,StreamExt};
+use std::collections::{HashMap,HashSet,VecDeque};
+use std::fmt::{self,Display};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context,Poll};
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -9,6 +12,7 @@ use gethostname::gethostname;
 use lapin::{
     options::*,
     types::FieldTable,
+    BasicProperties,
     Channel,
     Connection,
     ConnectionProperties,
@@ -16,7 +20,8 @@ use lapin::{
     publisher_confirm::PublisherConfirm,
     Result as LapinResult
 };
-use serde_json::Value;
+use serde_json::{json,Value};
+use tokio::sync::broadcast::{channel as broadcast_channel,Receiver as BroadcastReceiver,Sender as BroadcastSender};
 use tokio::sync::mpsc::{unbounded_channel,UnboundedReceiver,UnboundedSender};
 use tokio::sync::oneshot::{channel as oneshot_channel,Sender as OneshotSender};
 use tokio::task::JoinHandle;
@@ -25,16 +30,47 @@ use tokio::time::timeout;
 use uuid::Uuid;
 
 use crate::AsyncResult;
+use crate::codec::{self,Codec};
+use crate::compression::{Compression,Compressor};
 use crate::rpc;
 use crate::Client as ClientTrait;
 
+use super::control::{control_exchange,declare_control_exchange};
+use super::decode_responses;
+use super::with_meta;
+
+const ERRORS_BUFFER : usize = 64;
+
+// Events can overtake the reply that starts their subscription, as workers
+// publish them on another channel, so some are held while any subscription
+// is still starting.
+const EARLY_EVENTS_BACKLOG : usize = 1024;
+
+/// The partial results of a streamed call, followed by its final result
+/// unless that's null. An error response, or a gap of longer than the
+/// timeout between items or progress reports, ends the stream with an error.
+pub type ResultStream = Pin<Box<dyn Stream<Item = AsyncResult<Value>> + Send>>;
+
+// Everything received for a streamed call, in the order it arrived.
+#[derive(Debug)]
+enum StreamEvent {
+    Partial(rpc::PartialResult),
+    Progress(rpc::Progress),
+    Final(rpc::Response),
+    Event(Value)
+}
+
 #[derive(Clone,Debug)]
 pub struct ClientOptions {
     pub amqp_url: String,
     pub queue_name: String,
     pub ident: String,
     pub timeout: Duration,
-    pub threads: usize
+    pub threads: usize,
+    pub codec: Arc<dyn Codec>,
+    pub strictness: rpc::Strictness,
+    pub compressor: Compressor,
+    pub meta: rpc::Meta
 }
 
 impl Default for ClientOptions {
@@ -44,7 +80,11 @@ impl Default for ClientOptions {
             queue_name: "skein_rpc".to_string(),
             ident: "skein".to_string(),
             timeout: Duration::from_secs(30),
-            threads: 8
+            threads: 8,
+            codec: Arc::new(codec::Json),
+            strictness: rpc::Strictness::default(),
+            compressor: Compressor::default(),
+            meta: rpc::Meta::new()
         }
     }
 }
@@ -56,7 +96,11 @@ impl ClientOptions {
             queue_name: queue_name.to_string(),
             ident: ident.to_string(),
             timeout: Duration::from_secs(30),
-            threads: 8
+            threads: 8,
+            codec: Arc::new(codec::Json),
+            strictness: rpc::Strictness::default(),
+            compressor: Compressor::default(),
+            meta: rpc::Meta::new()
         }
     }
 
@@ -89,9 +133,50 @@ impl ClientOptions {
 
         self
     }
+
+    pub fn with_codec(mut self, codec: impl Codec + 'static) -> Self {
+        self.codec = Arc::new(codec);
+
+        self
+    }
+
+    pub fn with_strictness(mut self, strictness: rpc::Strictness) -> Self {
+        self.strictness = strictness;
+
+        self
+    }
+
+    /// Compresses requests at or above the compression threshold.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compressor.compression = Some(compression);
+
+        self
+    }
+
+    pub fn with_compression_threshold(mut self, threshold: usize) -> Self {
+        self.compressor.threshold = threshold;
+
+        self
+    }
+
+    /// Discards responses which decompress to more than the given number of
+    /// bytes.
+    pub fn with_max_decompressed(mut self, max_decompressed: usize) -> Self {
+        self.compressor.max_decompressed = max_decompressed;
+
+        self
+    }
+
+    /// Adds meta sent with every request, where any meta set on the request
+    /// itself takes precedence.
+    pub fn with_meta(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.meta.insert(key.to_string(), value.to_string());
+
+        self
+    }
 }
 
-async fn declare_queues(options: &ClientOptions, channel: &Channel, ident: &String) -> LapinResult<()> {
+async fn declare_queues(options: &ClientOptions, channel: &Channel, ident: &str) -> LapinResult<()> {
     let queue_name = options.queue_name.to_string();
 
     channel.queue_declare(
@@ -107,7 +192,7 @@ async fn declare_queues(options: &ClientOptions, channel: &Channel, ident: &Stri
     ).await?;
 
     channel.queue_declare(
-        ident.as_str(),
+        ident,
         QueueDeclareOptions {
             passive: false,
             durable: false,
@@ -118,6 +203,8 @@ async fn declare_queues(options: &ClientOptions, channel: &Channel, ident: &Stri
         FieldTable::default()
     ).await?;
 
+    declare_control_exchange(channel, queue_name.as_str()).await?;
+
     Ok(())
 }
 
@@ -145,13 +232,16 @@ async fn create_consumer(loop_context: &ClientLoopContext) -> LapinResult<(Chann
 
 async fn client_consumer_loop(channel: Channel, mut consumer: Consumer, loop_context: &mut ClientLoopContext) -> LapinResult<()> {
     let rpc_queue_name = loop_context.options.queue_name.to_string();
+    let control_exchange = control_exchange(&rpc_queue_name);
     let reply_to = loop_context.ident.clone();
 
     loop {
         tokio::select!(
             c = loop_context.confirm_rx.recv() => {
-                if let Some((confirm,command)) = c {
+                if let Some((confirm,mut command)) = c {
                     if let Err(err) = confirm.await {
+                        loop_context.unregister(&mut command);
+
                         if let Err(err) = loop_context.tx.send(command) {
                             log::error!("Error requeueing message: {}", err);
                         }
@@ -164,19 +254,38 @@ async fn client_consumer_loop(channel: Channel, mut consumer: Consumer, loop_con
 
                     loop_context.confirmations += 1;
 
+                    log::trace!("{} delivery {} published to {}", command, &loop_context.confirmations, &rpc_queue_name);
+
                     match command {
                         ClientCommand::Request(request, reply) => {
-                            log::trace!("{}> Delivery {} published to {}", request.id(), &loop_context.confirmations, &rpc_queue_name);
-
-                            loop_context.requests.insert(request.id().clone(), reply);
+                            if let Some(id) = request.id() {
+                                loop_context.requests.insert(id.clone(), reply);
+                            }
                         },
                         ClientCommand::Inject(request, reply) => {
-                            log::trace!("{}> Delivery {} published to {}", request.id(), &loop_context.confirmations, &rpc_queue_name);
-
-                            if let Err(_) = reply.send(request.id().clone()) {
-                                log::error!("{}> Error sending reply", request.id());
+                            if reply.send(request.id().map(ToString::to_string).unwrap_or_default()).is_err() {
+                                log::error!("{}> Error sending reply", request);
                             }
                         },
+                        ClientCommand::Notify(request, reply) => {
+                            if reply.send(()).is_err() {
+                                log::error!("{}> Error sending reply", request);
+                            }
+                        },
+                        ClientCommand::Batch(_, _) | ClientCommand::Stream(_, _) | ClientCommand::Subscribe(_, _) => {
+                            // Registered when published so no replies or
+                            // partial results are missed.
+                        },
+                        ClientCommand::Detach(_) => {
+                            // Never published.
+                        },
+                        ClientCommand::Cancel(id) => {
+                            // Any reply is no longer wanted.
+                            loop_context.requests.remove(&id);
+                            loop_context.streams.remove(&id);
+                            loop_context.subscribing.remove(&id);
+                            loop_context.correlations.remove(&id.to_string());
+                        },
                         ClientCommand::Terminate => {
                             // Not sure how this would get here.
                         }
@@ -185,59 +294,50 @@ async fn client_consumer_loop(channel: Channel, mut consumer: Consumer, loop_con
             },
             r = loop_context.rx.recv() => {
                 match r {
-                    Some(ClientCommand::Request(request,reply)) => {
-                        match serde_json::to_string(&request) {
-                            Ok(str) => {
-                                log::trace!("{}> Publishing", request.id());
+                    Some(ClientCommand::Terminate) => {
+                        log::trace!("Client terminated, exiting client loop");
 
-                                match channel.basic_publish(
-                                    "", // FUTURE: Allow specifying exchange
-                                    rpc_queue_name.as_str(),
-                                    Default::default(),
-                                    str.as_bytes(),
-                                    request.properties(reply_to.as_str())
-                                ).await {
-                                    Ok(confirm) => {
-                                        if let Err(_) = loop_context.confirm_tx.send((confirm, ClientCommand::Request(request,reply))) {
-                                            log::error!("Error pushing to confirmation queue");
-                                        }
-                                    },
-                                    Err(err) => {
-                                        if let Err(err) = loop_context.tx.send(ClientCommand::Request(request,reply)) {
-                                            log::error!("Error requeueing message: {}", err);
-                                        }
-                                        else {
-                                            loop_context.retried += 1;
-                                        }
+                        return Ok(());
+                    },
+                    Some(ClientCommand::Detach(subscription)) => {
+                        log::trace!("Subscription {} detached", subscription);
 
-                                        return Err(err);
-                                    }
-                                }
-                            },
-                            Err(err) => {
-                                log::error!("Error serializing request: {}", err);
-                            }
-                        }
+                        loop_context.subscriptions.remove(&subscription);
                     },
-                    Some(ClientCommand::Inject(request,reply)) => {
-                        log::trace!("{}> Request injection", request.id());
+                    Some(mut command) => {
+                        log::trace!("{} publishing", command);
 
-                        match serde_json::to_string(&request) {
-                            Ok(str) => {
+                        if let Some((correlation_id, ids)) = command.correlation() {
+                            loop_context.correlations.insert(correlation_id, ids);
+                        }
+
+                        loop_context.register(&mut command);
+
+                        // Cancellations go to every worker, as there's no
+                        // telling which one has the request.
+                        let (exchange, routing_key) = match command {
+                            ClientCommand::Cancel(_) => (control_exchange.as_str(), ""),
+                            _ => ("", rpc_queue_name.as_str()) // FUTURE: Allow specifying exchange
+                        };
+
+                        match command.encode(reply_to.as_str(), &loop_context.options) {
+                            Ok((payload, properties)) => {
                                 match channel.basic_publish(
-                                    "", // FUTURE: Allow specifying exchange
-                                    rpc_queue_name.as_str(),
+                                    exchange,
+                                    routing_key,
                                     Default::default(),
-                                    str.as_bytes(),
-                                    request.properties("")
+                                    &payload,
+                                    properties
                                 ).await {
                                     Ok(confirm) => {
-                                        if let Err(_) = loop_context.confirm_tx.send((confirm, ClientCommand::Inject(request,reply))) {
+                                        if loop_context.confirm_tx.send((confirm, command)).is_err() {
                                             log::error!("Error pushing to confirmation queue");
                                         }
                                     },
                                     Err(err) => {
-                                        if let Err(err) = loop_context.tx.send(ClientCommand::Inject(request,reply)) {
+                                        loop_context.unregister(&mut command);
+
+                                        if let Err(err) = loop_context.tx.send(command) {
                                             log::error!("Error requeueing message: {}", err);
                                         }
                                         else {
@@ -250,14 +350,12 @@ async fn client_consumer_loop(channel: Channel, mut consumer: Consumer, loop_con
                             },
                             Err(err) => {
                                 log::error!("Error serializing request: {}", err);
+
+                                // Leaves the callers to find out.
+                                loop_context.unregister(&mut command);
                             }
                         }
                     },
-                    Some(ClientCommand::Terminate) => {
-                        log::trace!("Client terminated, exiting client loop");
-
-                        return Ok(());
-                    },
                     None => {
                         log::trace!("Channel closed, exiting client loop");
 
@@ -268,27 +366,25 @@ async fn client_consumer_loop(channel: Channel, mut consumer: Consumer, loop_con
             incoming = consumer.next() => {
                 match incoming {
                     Some(Ok(delivery)) => {
-                        match rpc::Response::try_from(&delivery) {
-                            Ok(response) => {
-                                match response.id() {
-                                    Some(id) => {
-                                        match loop_context.requests.remove(id) {
-                                            Some(reply) => {
-                                                reply.send(response).ok();
-                                            },
-                                            None => {
-                                                // Unknown request.
-                                                log::warn!("Warning: Received response for unknown request {}", id);
-                                            }
-                                        }
+                        match decode_responses(&delivery, loop_context.options.strictness, loop_context.options.compressor.max_decompressed) {
+                            Ok(payload) => {
+                                let correlated = delivery.properties.correlation_id().as_ref().and_then(|correlation_id| {
+                                    loop_context.correlations.remove(correlation_id.as_str())
+                                });
+
+                                loop_context.dispatch_payload(payload, correlated.unwrap_or_default());
+                            },
+                            Err(err) => {
+                                // Anything else should be a notification tied
+                                // to a pending request.
+                                match rpc::Request::try_from(&delivery) {
+                                    Ok(notification) if notification.is_notification() => {
+                                        loop_context.dispatch_notification(notification);
                                     },
-                                    None => {
-                                        log::error!("Missing ID error: {:?}", response);
+                                    _ => {
+                                        log::error!("Error creating Response from Delivery: {:?}", err);
                                     }
                                 }
-                            },
-                            Err(err) => {
-                                log::error!("Error creating Response from Delivery: {:?}", err);
                             }
                         }
 
@@ -325,16 +421,211 @@ struct ClientLoopContext {
     confirm_rx: UnboundedReceiver<(PublisherConfirm,ClientCommand)>,
     tx: UnboundedSender<ClientCommand>,
     rx: UnboundedReceiver<ClientCommand>,
-    requests: HashMap::<String,OneshotSender<rpc::Response>>
+    requests: HashMap::<rpc::Id,OneshotSender<rpc::Response>>,
+    streams: HashMap::<rpc::Id,UnboundedSender<StreamEvent>>,
+    subscribing: HashSet::<rpc::Id>,
+    subscriptions: HashMap::<String,UnboundedSender<StreamEvent>>,
+    early_events: VecDeque::<rpc::SubscriptionEvent>,
+    correlations: HashMap::<String,Vec<rpc::Id>>,
+    errors: BroadcastSender<rpc::Response>
 }
 
 impl ClientLoopContext {
+    // Dispatches each response in a reply to the message with the given
+    // requests. Errors that can't be tied to one of them, with a null or
+    // unknown id, are given to those still waiting, as a worker does when a
+    // request can't be parsed, which would otherwise leave them waiting until
+    // they time out.
+    fn dispatch_payload(&mut self, payload: rpc::Payload<rpc::Response>, correlated: Vec<rpc::Id>) {
+        let mut unmatched = None;
+
+        for response in payload.into_items() {
+            if unmatched.is_none() && response.is_error() && !correlated.is_empty() && !correlated.contains(response.id()) {
+                unmatched = Some(response);
+            }
+            else {
+                self.dispatch(response);
+            }
+        }
+
+        let response = match unmatched {
+            Some(response) => response,
+            None => return
+        };
+
+        let waiting : Vec<&rpc::Id> = correlated.iter().filter(|id| self.requests.contains_key(id) || self.streams.contains_key(id)).collect();
+
+        match response.error() {
+            Some(error) if !waiting.is_empty() => {
+                for id in waiting {
+                    self.dispatch(rpc::Response::new_error(id.clone(), error.clone()));
+                }
+            },
+            _ => self.dispatch(response)
+        }
+    }
+
+    fn dispatch(&mut self, response: rpc::Response) {
+        match response.id() {
+            rpc::Id::Null => {
+                log::error!("Missing ID error: {:?}", response);
+
+                // Nobody may be listening, in which case this is dropped.
+                self.errors.send(response).ok();
+            },
+            id => {
+                if let Some(events) = self.streams.remove(id) {
+                    // Subscriptions are routed as soon as their id is known
+                    // so no events are missed.
+                    let subscription = if self.subscribing.remove(id) {
+                        response.result().and_then(Value::as_str).map(str::to_string)
+                    }
+                    else {
+                        None
+                    };
+
+                    if let Some(subscription) = &subscription {
+                        self.subscriptions.insert(subscription.clone(), events.clone());
+                    }
+
+                    events.send(StreamEvent::Final(response)).ok();
+
+                    // Then any events that arrived ahead of it.
+                    if let Some(subscription) = subscription {
+                        for event in self.take_early_events(&subscription) {
+                            events.send(StreamEvent::Event(event.result)).ok();
+                        }
+                    }
+
+                    if self.subscribing.is_empty() {
+                        self.early_events.clear();
+                    }
+
+                    return;
+                }
+
+                match self.requests.remove(id) {
+                    Some(reply) => {
+                        reply.send(response).ok();
+                    },
+                    None if response.error().map(|e| e.error_code()) == Some(rpc::ErrorCode::RequestCancelled) => {
+                        log::trace!("Request {} cancelled", id);
+                    },
+                    None => {
+                        // Unknown request.
+                        log::warn!("Warning: Received response for unknown request {}", id);
+                    }
+                }
+            }
+        }
+    }
+
+    fn dispatch_notification(&mut self, notification: rpc::Request) {
+        if let Some(partial) = notification.partial_result() {
+            match self.streams.get(&partial.id) {
+                Some(events) => {
+                    events.send(StreamEvent::Partial(partial)).ok();
+                },
+                None => {
+                    log::warn!("Warning: Received partial result for unknown request {}", partial.id);
+                }
+            }
+        }
+        else if let Some((id, progress)) = notification.progress() {
+            match self.streams.get(&id) {
+                Some(events) => {
+                    events.send(StreamEvent::Progress(progress)).ok();
+                },
+                None => {
+                    // Only callers that asked for progress are told about it.
+                    log::trace!("Progress on request {}: {}", id, progress);
+                }
+            }
+        }
+        else if let Some(event) = notification.subscription_event() {
+            match self.subscriptions.get(&event.subscription) {
+                Some(events) => {
+                    if events.send(StreamEvent::Event(event.result)).is_err() {
+                        self.subscriptions.remove(&event.subscription);
+                    }
+                },
+                None if !self.subscribing.is_empty() => {
+                    if self.early_events.len() >= EARLY_EVENTS_BACKLOG {
+                        self.early_events.pop_front();
+                    }
+
+                    self.early_events.push_back(event);
+                },
+                None => {
+                    log::warn!("Warning: Received event for unknown subscription {}", event.subscription);
+                }
+            }
+        }
+        else {
+            log::warn!("Warning: Received unsupported notification {}", notification);
+        }
+    }
+
+    // Registers where replies to a command go as it's published, rather than
+    // once confirmed, as replies can arrive ahead of the confirmation.
+    fn register(&mut self, command: &mut ClientCommand) {
+        match command {
+            ClientCommand::Batch(batch, replies) => {
+                for (request, reply) in batch.iter().zip(replies.drain(..)) {
+                    if let Some(id) = request.id() {
+                        self.requests.insert(id.clone(), reply);
+                    }
+                }
+            },
+            ClientCommand::Stream(request, events) => {
+                if let Some(id) = request.id() {
+                    self.streams.insert(id.clone(), events.clone());
+                }
+            },
+            ClientCommand::Subscribe(request, events) => {
+                if let Some(id) = request.id() {
+                    self.streams.insert(id.clone(), events.clone());
+                    self.subscribing.insert(id.clone());
+                }
+            },
+            _ => ()
+        }
+    }
+
+    // Takes back what was registered when publishing a command that didn't
+    // go out, so it can be retried as it was.
+    fn unregister(&mut self, command: &mut ClientCommand) {
+        match command {
+            ClientCommand::Batch(batch, replies) => {
+                // Notifications never had a reply registered.
+                *replies = batch.iter().map(|request| {
+                    request.id().and_then(|id| self.requests.remove(id)).unwrap_or_else(|| oneshot_channel().0)
+                }).collect();
+            },
+            ClientCommand::Stream(request, _) | ClientCommand::Subscribe(request, _) => {
+                if let Some(id) = request.id() {
+                    self.streams.remove(id);
+                    self.subscribing.remove(id);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    fn take_early_events(&mut self, subscription: &str) -> Vec<rpc::SubscriptionEvent> {
+        let (taken, kept) : (VecDeque<_>, VecDeque<_>) = self.early_events.drain(..).partition(|event| event.subscription == subscription);
+
+        self.early_events = kept;
+
+        taken.into()
+    }
+
     fn report(&self) -> ClientReport {
         ClientReport {
             connections: self.connections,
             confirmations: self.confirmations,
             retried: self.retried,
-            pending: self.requests.len()
+            pending: self.requests.len() + self.streams.len()
         }
     }
 }
@@ -360,6 +651,11 @@ async fn client_handle(mut loop_context: ClientLoopContext) -> LapinResult<JoinH
                         Ok(_) => break,
                         Err(err) => {
                             log::error!("Error in consumer loop: {}", err);
+
+                            // The reply queue goes away with the connection,
+                            // and workers drop the subscribers along with it.
+                            loop_context.subscriptions.clear();
+                            loop_context.early_events.clear();
                         }
                     }
                 },
@@ -386,12 +682,154 @@ async fn connect(options: &ClientOptions) -> LapinResult<Connection> {
 enum ClientCommand {
     Request(rpc::Request,OneshotSender<rpc::Response>),
     Inject(rpc::Request,OneshotSender<String>),
+    Notify(rpc::Request,OneshotSender<()>),
+    Batch(rpc::Batch<rpc::Request>,Vec<OneshotSender<rpc::Response>>),
+    Stream(rpc::Request,UnboundedSender<StreamEvent>),
+    Subscribe(rpc::Request,UnboundedSender<StreamEvent>),
+    Detach(String),
+    Cancel(rpc::Id),
     Terminate
 }
 
+impl ClientCommand {
+    // The correlation id the message is sent with, named after the first of
+    // the requests it carries that expect a reply, along with all of those.
+    fn correlation(&self) -> Option<(String, Vec<rpc::Id>)> {
+        let ids : Vec<rpc::Id> = match self {
+            Self::Request(request, _) | Self::Stream(request, _) | Self::Subscribe(request, _) => request.id().cloned().into_iter().collect(),
+            Self::Batch(batch, _) => batch.iter().filter_map(|r| r.id().cloned()).collect(),
+            _ => Vec::new()
+        };
+
+        Some((ids.first()?.to_string(), ids))
+    }
+
+    fn encode(&self, reply_to: &str, options: &ClientOptions) -> AsyncResult<(Vec<u8>,BasicProperties)> {
+        let codec = options.codec.as_ref();
+
+        let (payload, properties) = match self {
+            Self::Request(request, _) => (codec::encode(codec, request)?, request.properties(reply_to)),
+            Self::Inject(request, _) => (codec::encode(codec, request)?, request.properties(reply_to)),
+            Self::Notify(request, _) => (codec::encode(codec, request)?, request.properties(reply_to)),
+            Self::Batch(batch, _) => (codec::encode(codec, batch)?, batch.properties(reply_to)),
+            Self::Stream(request, _) => (codec::encode(codec, request)?, request.properties(reply_to)),
+            Self::Subscribe(request, _) => (codec::encode(codec, request)?, request.properties(reply_to)),
+            Self::Detach(_) => (Vec::new(), BasicProperties::default()),
+            Self::Cancel(id) => {
+                // Identifies the caller too, as only the pair is unique.
+                let request = rpc::Request::new_cancel(id, reply_to);
+
+                (codec::encode(codec, &request)?, request.properties(reply_to))
+            },
+            Self::Terminate => (Vec::new(), BasicProperties::default())
+        };
+
+        let properties = properties.with_content_type(codec.content_type().into());
+
+        // Returned with the reply so errors without an id can be tied to the
+        // requests sent.
+        let properties = match self.correlation() {
+            Some((correlation_id, _)) => properties.with_correlation_id(correlation_id.into()),
+            None => properties
+        };
+
+        // Meta set on the requests themselves is already present, and takes
+        // precedence over the defaults.
+        let properties = with_meta(properties, &options.meta);
+
+        match options.compressor.apply(payload)? {
+            (payload, Some(content_encoding)) => Ok((payload, properties.with_content_encoding(content_encoding.into()))),
+            (payload, None) => Ok((payload, properties))
+        }
+    }
+}
+
+impl Display for ClientCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request(request, _) => write!(f, "Request {}", request),
+            Self::Inject(request, _) => write!(f, "Inject {}", request),
+            Self::Notify(request, _) => write!(f, "Notify {}", request),
+            Self::Batch(batch, _) => write!(f, "Batch of {}", batch.len()),
+            Self::Stream(request, _) => write!(f, "Stream {}", request),
+            Self::Subscribe(request, _) => write!(f, "Subscribe {}", request),
+            Self::Detach(subscription) => write!(f, "Detach {}", subscription),
+            Self::Cancel(id) => write!(f, "Cancel {}", id),
+            Self::Terminate => write!(f, "Terminate")
+        }
+    }
+}
+
+// Cancels the pending requests when dropped, which happens if the caller
+// gives up on the call or it times out, unless disarmed once replies arrive.
+struct CancelOnDrop {
+    rpc: UnboundedSender<ClientCommand>,
+    ids: Vec<rpc::Id>
+}
+
+impl CancelOnDrop {
+    fn new(rpc: UnboundedSender<ClientCommand>, ids: Vec<rpc::Id>) -> Self {
+        Self { rpc, ids }
+    }
+
+    fn disarm(mut self) {
+        self.ids.clear();
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        for id in self.ids.drain(..) {
+            // The client may already be closed, in which case there's nothing
+            // left to cancel.
+            self.rpc.send(ClientCommand::Cancel(id)).ok();
+        }
+    }
+}
+
+/// The events sent for a subscription. The stream ends if the connection is
+/// lost, as the subscription ends with it, and dropping it stops routing any
+/// further events without unsubscribing.
+#[derive(Debug)]
+pub struct Subscription {
+    id: String,
+    events: UnboundedReceiver<StreamEvent>,
+    rpc: UnboundedSender<ClientCommand>
+}
+
+impl Subscription {
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Value;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Value>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.events.poll_recv(cx) {
+                Poll::Ready(Some(StreamEvent::Event(result))) => return Poll::Ready(Some(result)),
+                Poll::Ready(Some(_)) => continue,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.rpc.send(ClientCommand::Detach(self.id.clone())).ok();
+    }
+}
+
 #[derive(Debug)]
 pub struct Client {
     rpc: UnboundedSender<ClientCommand>,
+    errors: BroadcastSender<rpc::Response>,
     options: ClientOptions,
     handle: JoinHandle<ClientReport>
 }
@@ -400,13 +838,14 @@ impl Client {
     pub async fn new(options: ClientOptions) -> LapinResult<Client> {
         let ident = format!(
             "{}-{}@{}",
-            options.ident.to_string(),
-            Uuid::new_v4().to_string(),
+            options.ident,
+            Uuid::new_v4(),
             gethostname().into_string().unwrap()
         );
 
         let (confirm_tx, confirm_rx) = unbounded_channel::<(PublisherConfirm,ClientCommand)>();
         let (tx, rx) = unbounded_channel::<ClientCommand>();
+        let (errors, _) = broadcast_channel::<rpc::Response>(ERRORS_BUFFER);
 
         let loop_context = ClientLoopContext {
             ident,
@@ -418,18 +857,31 @@ impl Client {
             confirm_rx,
             tx: tx.clone(),
             rx,
-            requests: HashMap::new()
+            requests: HashMap::new(),
+            streams: HashMap::new(),
+            subscribing: HashSet::new(),
+            subscriptions: HashMap::new(),
+            early_events: VecDeque::new(),
+            correlations: HashMap::new(),
+            errors: errors.clone()
         };
 
         Ok(
             Client {
                 rpc: tx,
+                errors,
                 options,
                 handle: client_handle(loop_context).await?
             }
         )
     }
 
+    /// Subscribes to error responses that can't be matched to a pending
+    /// request, such as parse errors reported by a worker with a null id.
+    pub fn subscribe_errors(&self) -> BroadcastReceiver<rpc::Response> {
+        self.errors.subscribe()
+    }
+
     pub fn into_handle(self) -> JoinHandle<ClientReport> {
         self.handle
     }
@@ -441,6 +893,159 @@ impl Client {
     pub fn close(&self) -> bool {
         self.rpc.send(ClientCommand::Terminate).is_ok()
     }
+
+    /// Makes a request where the worker streams back partial results ahead
+    /// of the final result. The timeout applies to the wait for each item
+    /// rather than the call as a whole, and the request is cancelled if the
+    /// stream is dropped before it ends. As such the request is sent without
+    /// a deadline, which would have the worker give up on it regardless.
+    pub fn rpc_stream(&self, method: impl ToString, params: Option<Value>) -> AsyncResult<ResultStream> {
+        let method = method.to_string();
+        let timeout_duration = self.options.timeout;
+
+        let request = rpc::Request::new(Uuid::new_v4().to_string(), &method, params);
+
+        log::trace!("{}> RPC Stream: {} (confirmations)", request, &method);
+
+        let (events_tx, events) = unbounded_channel::<StreamEvent>();
+        let cancel = CancelOnDrop::new(self.rpc.clone(), request.id().cloned().into_iter().collect());
+
+        self.rpc.send(ClientCommand::Stream(request, events_tx))?;
+
+        Ok(Box::pin(stream::unfold(Some((events, cancel, 0u64)), move |state| async move {
+            let (mut events, cancel, expected) = state?;
+
+            loop {
+                match timeout(timeout_duration, events.recv()).await {
+                    Ok(Some(StreamEvent::Partial(partial))) => {
+                        if partial.seq != expected {
+                            log::warn!("Warning: Partial result {} for {} arrived out of sequence, expected {}", partial.seq, partial.id, expected);
+                        }
+
+                        return Some((Ok(partial.value), Some((events, cancel, partial.seq + 1))));
+                    },
+                    Ok(Some(StreamEvent::Progress(progress))) => {
+                        // Progress only serves to keep the stream alive.
+                        log::trace!("Progress on stream: {}", progress);
+                    },
+                    Ok(Some(StreamEvent::Event(_))) => {
+                        // Only routed to subscriptions.
+                    },
+                    Ok(Some(StreamEvent::Final(response))) => {
+                        cancel.disarm();
+
+                        return match response {
+                            rpc::Response::Result { result: Value::Null, .. } => None,
+                            rpc::Response::Result { result, .. } => Some((Ok(result), None)),
+                            rpc::Response::Error { error, .. } => Some((Err(Box::new(error) as Box<dyn std::error::Error + Send + Sync>), None))
+                        };
+                    },
+                    Ok(None) => return Some((Err("Stream closed before a response was received".into()), None)),
+                    Err(err) => return Some((Err(err.into()), None))
+                }
+            }
+        })))
+    }
+
+    /// Makes a request where any progress the worker reports is sent to the
+    /// given channel. The timeout applies to the wait between reports rather
+    /// than the call as a whole, so slow work that's still progressing isn't
+    /// abandoned, and for the same reason the request is sent without a
+    /// deadline. It's cancelled instead if the caller stops waiting.
+    pub async fn rpc_request_with_progress(&self, method: impl ToString, params: Option<Value>, progress: UnboundedSender<rpc::Progress>) -> AsyncResult<Value> {
+        let method = method.to_string();
+
+        let request = rpc::Request::new(Uuid::new_v4().to_string(), &method, params);
+
+        log::trace!("{}> RPC Request: {} (progress)", request, &method);
+
+        let (events_tx, mut events) = unbounded_channel::<StreamEvent>();
+        let cancel = CancelOnDrop::new(self.rpc.clone(), request.id().cloned().into_iter().collect());
+
+        self.rpc.send(ClientCommand::Stream(request, events_tx))?;
+
+        loop {
+            match timeout(self.options.timeout, events.recv()).await? {
+                Some(StreamEvent::Progress(report)) => {
+                    // Nobody may be listening, which is fine.
+                    progress.send(report).ok();
+                },
+                Some(StreamEvent::Partial(partial)) => {
+                    log::warn!("Warning: Discarding partial result {} for {}, use rpc_stream instead", partial.seq, partial.id);
+                },
+                Some(StreamEvent::Event(_)) => {
+                    // Only routed to subscriptions.
+                },
+                Some(StreamEvent::Final(response)) => {
+                    cancel.disarm();
+
+                    return match response {
+                        rpc::Response::Result { result, .. } => Ok(result),
+                        rpc::Response::Error { error, .. } => Err(Box::new(error))
+                    };
+                },
+                None => {
+                    return Err("Request closed before a response was received".into());
+                }
+            }
+        }
+    }
+
+    /// Calls a method which starts a subscription, such as one using
+    /// Sink::subscribe, where the result is the subscription id. Events for
+    /// it are received through the returned Subscription. The request is sent
+    /// without a deadline, as one would otherwise be handed on to whatever
+    /// goes on to send the events, and is cancelled if the caller gives up.
+    pub async fn subscribe(&self, method: impl ToString, params: Option<Value>) -> AsyncResult<Subscription> {
+        let method = method.to_string();
+
+        let request = rpc::Request::new(Uuid::new_v4().to_string(), &method, params);
+
+        log::trace!("{}> RPC Subscribe: {} (confirmations)", request, &method);
+
+        let (events_tx, mut events) = unbounded_channel::<StreamEvent>();
+        let cancel = CancelOnDrop::new(self.rpc.clone(), request.id().cloned().into_iter().collect());
+
+        self.rpc.send(ClientCommand::Subscribe(request, events_tx))?;
+
+        loop {
+            match timeout(self.options.timeout, events.recv()).await? {
+                Some(StreamEvent::Final(response)) => {
+                    cancel.disarm();
+
+                    return match response {
+                        rpc::Response::Result { result: Value::String(id), .. } => {
+                            Ok(Subscription { id, events, rpc: self.rpc.clone() })
+                        },
+                        rpc::Response::Result { result, .. } => {
+                            Err(format!("Expected a subscription id, not {}", result).into())
+                        },
+                        rpc::Response::Error { error, .. } => Err(Box::new(error))
+                    };
+                },
+                Some(_) => {
+                    // Nothing else is expected ahead of the subscription id.
+                },
+                None => {
+                    return Err("Request closed before a response was received".into());
+                }
+            }
+        }
+    }
+
+    /// Ends a subscription by calling the given method with its id as the
+    /// only param.
+    pub async fn unsubscribe(&self, method: impl ToString + Send, subscription: Subscription) -> AsyncResult<Value> {
+        self.rpc_request(method, Some(json!([ subscription.id() ]))).await
+    }
+
+    /// Asks the worker processing the request with the given id to abandon
+    /// it, such as one sent with rpc_request_inject. Requests made through
+    /// the Client trait are cancelled automatically if the caller stops
+    /// waiting for them.
+    pub fn cancel(&self, id: impl Into<rpc::Id>) -> bool {
+        self.rpc.send(ClientCommand::Cancel(id.into())).is_ok()
+    }
 }
 
 #[async_trait]
@@ -449,13 +1054,19 @@ impl ClientTrait for Client {
         let (reply, responder) = oneshot_channel::<rpc::Response>();
         let method = method.to_string();
 
-        let request = rpc::Request::new_serialize(Uuid::new_v4().to_string(), &method, params);
+        let request = rpc::Request::new_serialize(Uuid::new_v4().to_string(), &method, params).with_timeout(self.options.timeout);
 
-        log::trace!("{}> RPC Request: {} (confirmations)", request.id(), &method);
+        log::trace!("{}> RPC Request: {} (confirmations)", request, &method);
+
+        let cancel = CancelOnDrop::new(self.rpc.clone(), request.id().cloned().into_iter().collect());
 
         self.rpc.send(ClientCommand::Request(request, reply))?;
 
-        match timeout(self.options.timeout, responder).await?? {
+        let response = timeout(self.options.timeout, responder).await??;
+
+        cancel.disarm();
+
+        match response {
             rpc::Response::Result { result, .. } => {
                 Ok(result.into())
             },
@@ -469,13 +1080,19 @@ impl ClientTrait for Client {
         let (reply, responder) = oneshot_channel::<rpc::Response>();
         let method = method.to_string();
 
-        let request = rpc::Request::new(Uuid::new_v4().to_string(), &method, params);
+        let request = rpc::Request::new(Uuid::new_v4().to_string(), &method, params).with_timeout(self.options.timeout);
+
+        log::trace!("{}> RPC Request: {} (confirmations)", request, &method);
 
-        log::trace!("{}> RPC Request: {} (confirmations)", request.id(), &method);
+        let cancel = CancelOnDrop::new(self.rpc.clone(), request.id().cloned().into_iter().collect());
 
         self.rpc.send(ClientCommand::Request(request, reply))?;
 
-        match timeout(self.options.timeout, responder).await?? {
+        let response = timeout(self.options.timeout, responder).await??;
+
+        cancel.disarm();
+
+        match response {
             rpc::Response::Result { result, .. } => {
                 Ok(result)
             },
@@ -485,12 +1102,45 @@ impl ClientTrait for Client {
         }
     }
 
+    async fn rpc_batch(&self, calls: Vec<(String, Option<Value>)>) -> AsyncResult<Vec<Result<Value, rpc::ErrorResponse>>> {
+        let mut batch = rpc::Batch::new(Vec::with_capacity(calls.len()));
+        let mut replies = Vec::with_capacity(calls.len());
+        let mut responders = Vec::with_capacity(calls.len());
+
+        for (method, params) in calls {
+            let (reply, responder) = oneshot_channel::<rpc::Response>();
+
+            batch.push(rpc::Request::new(Uuid::new_v4().to_string(), method, params).with_timeout(self.options.timeout));
+            replies.push(reply);
+            responders.push(responder);
+        }
+
+        log::trace!("RPC Batch: {} request(s)", batch.len());
+
+        let cancel = CancelOnDrop::new(self.rpc.clone(), batch.iter().filter_map(|r| r.id().cloned()).collect());
+
+        self.rpc.send(ClientCommand::Batch(batch, replies))?;
+
+        let responses = timeout(self.options.timeout, try_join_all(responders)).await??;
+
+        cancel.disarm();
+
+        Ok(
+            responses.into_iter().map(|response| {
+                match response {
+                    rpc::Response::Result { result, .. } => Ok(result),
+                    rpc::Response::Error { error, .. } => Err(error)
+                }
+            }).collect()
+        )
+    }
+
     async fn rpc_request_inject(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<String> {
         let method = method.to_string();
 
         let request = rpc::Request::new_noreply(Uuid::new_v4().to_string(), &method, params);
 
-        log::trace!("{}> RPC Request: {} (confirmations)", request.id(), &method);
+        log::trace!("{}> RPC Request: {} (confirmations)", request, &method);
 
         let (reply, responder) = oneshot_channel::<String>();
 
@@ -498,12 +1148,186 @@ impl ClientTrait for Client {
 
         Ok(responder.await?)
     }
+
+    async fn rpc_notify(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<()> {
+        let request = rpc::Request::new_notification(method, params);
+
+        log::trace!("RPC Notification: {} (confirmations)", request.method());
+
+        let (reply, responder) = oneshot_channel::<()>();
+
+        self.rpc.send(ClientCommand::Notify(request,reply))?;
+
+        Ok(responder.await?)
+    }
+
+    async fn rpc_send(&self, request: rpc::Request) -> AsyncResult<Option<rpc::Response>> {
+        if request.is_notification() || !request.reply_to() {
+            log::trace!("{}> RPC Send: {} (no reply)", request, request.method());
+
+            let (reply, responder) = oneshot_channel::<()>();
+
+            self.rpc.send(ClientCommand::Notify(request, reply))?;
+
+            responder.await?;
+
+            return Ok(None);
+        }
+
+        // The deadline is stamped here unless the caller has set one of their
+        // own, so workers can skip requests that have been given up on.
+        let request = match request.deadline() {
+            Some(_) => request,
+            None => request.with_timeout(self.options.timeout)
+        };
+
+        log::trace!("{}> RPC Send: {} (confirmations)", request, request.method());
+
+        let (reply, responder) = oneshot_channel::<rpc::Response>();
+
+        let cancel = CancelOnDrop::new(self.rpc.clone(), request.id().cloned().into_iter().collect());
+
+        self.rpc.send(ClientCommand::Request(request, reply))?;
+
+        let response = timeout(self.options.timeout, responder).await??;
+
+        cancel.disarm();
+
+        Ok(Some(response))
+    }
 }
 
 #[cfg(test)]
 mod test {
-    // use super::*;
+    use super::*;
+
+    fn loop_context() -> ClientLoopContext {
+        let (confirm_tx, confirm_rx) = unbounded_channel();
+        let (tx, rx) = unbounded_channel();
+        let (errors, _) = broadcast_channel(ERRORS_BUFFER);
+
+        ClientLoopContext {
+            ident: "test".to_string(),
+            options: ClientOptions::default(),
+            connections: 0,
+            confirmations: 0,
+            retried: 0,
+            confirm_tx,
+            confirm_rx,
+            tx,
+            rx,
+            requests: HashMap::new(),
+            streams: HashMap::new(),
+            subscribing: HashSet::new(),
+            subscriptions: HashMap::new(),
+            early_events: VecDeque::new(),
+            correlations: HashMap::new(),
+            errors
+        }
+    }
+
+    #[test]
+    fn test_dispatch_unmatched_errors() {
+        let mut loop_context = loop_context();
+        let mut errors = loop_context.errors.subscribe();
+
+        let ids = vec![ rpc::Id::from("a"), rpc::Id::from("b"), rpc::Id::from("c") ];
+        let mut responders = Vec::new();
+
+        for id in &ids {
+            let (reply, responder) = oneshot_channel();
+
+            loop_context.requests.insert(id.clone(), reply);
+            responders.push(responder);
+        }
+
+        // A worker replying to a batch with an entry it couldn't parse.
+        let error = rpc::ErrorResponse::new(rpc::ErrorCode::InvalidRequest, "Invalid request", None);
+
+        loop_context.dispatch_payload(
+            rpc::Payload::Batch(rpc::Batch::new(vec![ rpc::Response::new_result("a", json!(1)), rpc::Response::new_error(rpc::Id::Null, error.clone()) ])),
+            ids.clone()
+        );
+
+        let responses : Vec<rpc::Response> = responders.into_iter().map(|mut r| r.try_recv().unwrap()).collect();
+
+        assert_eq!(responses[0], rpc::Response::new_result("a", json!(1)));
+        assert_eq!(responses[1], rpc::Response::new_error("b", error.clone()));
+        assert_eq!(responses[2], rpc::Response::new_error("c", error.clone()));
+        assert!(loop_context.requests.is_empty());
+        assert!(errors.try_recv().is_err());
+
+        // Without any correlated requests waiting it's reported as before.
+        loop_context.dispatch_payload(rpc::Payload::Single(rpc::Response::new_error(rpc::Id::Null, error.clone())), Vec::new());
 
-    // #[test]
+        assert_eq!(errors.try_recv().unwrap(), rpc::Response::new_error(rpc::Id::Null, error));
+    }
+
+    #[test]
+    fn test_early_events() {
+        let mut loop_context = loop_context();
+        let (events, mut receiver) = unbounded_channel();
+        let id = rpc::Id::from("a");
+
+        loop_context.streams.insert(id.clone(), events);
+        loop_context.subscribing.insert(id.clone());
+
+        // Events overtaking the reply that gives their subscription id.
+        loop_context.dispatch_notification(rpc::Request::new_subscription_event("s1", json!(1)));
+        loop_context.dispatch_notification(rpc::Request::new_subscription_event("other", json!(2)));
+        loop_context.dispatch(rpc::Response::new_result("a", json!("s1")));
+        loop_context.dispatch_notification(rpc::Request::new_subscription_event("s1", json!(3)));
+
+        assert!(matches!(receiver.try_recv(), Ok(StreamEvent::Final(_))));
+        assert!(matches!(receiver.try_recv(), Ok(StreamEvent::Event(value)) if value == json!(1)));
+        assert!(matches!(receiver.try_recv(), Ok(StreamEvent::Event(value)) if value == json!(3)));
+        assert!(receiver.try_recv().is_err());
+
+        // Those for no subscription that was starting are dropped.
+        assert!(loop_context.early_events.is_empty());
+
+        loop_context.dispatch_notification(rpc::Request::new_subscription_event("other", json!(4)));
+
+        assert!(loop_context.early_events.is_empty());
+    }
+
+    #[test]
+    fn test_register_batch() {
+        let mut loop_context = loop_context();
+        let (reply, mut responder) = oneshot_channel();
+        let (notified, _) = oneshot_channel();
+
+        let batch = rpc::Batch::new(vec![ rpc::Request::new("a", "echo", None), rpc::Request::new_notification("b", None) ]);
+        let mut command = ClientCommand::Batch(batch, vec![ reply, notified ]);
 
+        // Replies can be dispatched as soon as the batch is published.
+        loop_context.register(&mut command);
+
+        assert!(loop_context.requests.contains_key(&rpc::Id::from("a")));
+
+        // One that fails to publish is taken back intact to be retried.
+        loop_context.unregister(&mut command);
+
+        assert!(loop_context.requests.is_empty());
+        assert!(matches!(&command, ClientCommand::Batch(_, replies) if replies.len() == 2));
+
+        loop_context.register(&mut command);
+        loop_context.dispatch(rpc::Response::new_result("a", json!(1)));
+
+        assert_eq!(responder.try_recv().unwrap(), rpc::Response::new_result("a", json!(1)));
+    }
+
+    #[test]
+    fn test_correlation() {
+        let (reply, _) = oneshot_channel();
+        let command = ClientCommand::Request(rpc::Request::new("a", "echo", None), reply);
+
+        assert_eq!(command.correlation(), Some(("a".to_string(), vec![ rpc::Id::from("a") ])));
+
+        let batch = rpc::Batch::new(vec![ rpc::Request::new("a", "echo", None), rpc::Request::new_notification("b", None), rpc::Request::new("c", "echo", None) ]);
+        let command = ClientCommand::Batch(batch, Vec::new());
+
+        assert_eq!(command.correlation(), Some(("a".to_string(), vec![ rpc::Id::from("a"), rpc::Id::from("c") ])));
+        assert_eq!(ClientCommand::Cancel(rpc::Id::from("a")).correlation(), None);
+    }
 }
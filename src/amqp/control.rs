@@ -0,0 +1,115 @@
+use lapin::{
+    options::*,
+    types::FieldTable,
+    message::Delivery,
+    Channel,
+    Consumer,
+    ExchangeKind,
+    Result as LapinResult
+};
+
+use crate::compression;
+use crate::rpc;
+
+use super::decode_requests;
+
+/// Returns the name of the fanout exchange which carries control messages,
+/// such as cancellations, to every worker consuming from the queue.
+pub fn control_exchange(queue_name: &str) -> String {
+    format!("{}.control", queue_name)
+}
+
+/// Declares the control exchange. Both clients and workers do this, since
+/// publishing to an exchange that doesn't exist closes the channel.
+pub(super) async fn declare_control_exchange(channel: &Channel, queue_name: &str) -> LapinResult<String> {
+    let exchange = control_exchange(queue_name);
+
+    channel.exchange_declare(
+        exchange.as_str(),
+        ExchangeKind::Fanout,
+        ExchangeDeclareOptions {
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            internal: false,
+            nowait: false
+        },
+        FieldTable::default()
+    ).await?;
+
+    Ok(exchange)
+}
+
+/// Binds a private queue to the control exchange and consumes from it.
+/// Control messages are only relevant while the worker is running, so the
+/// queue goes away with the worker and nothing is acknowledged.
+pub(super) async fn control_consumer(channel: &Channel, queue_name: &str) -> LapinResult<Consumer> {
+    let exchange = declare_control_exchange(channel, queue_name).await?;
+
+    let queue = channel.queue_declare(
+        "",
+        QueueDeclareOptions {
+            passive: false,
+            durable: false,
+            exclusive: true,
+            auto_delete: true,
+            nowait: false
+        },
+        FieldTable::default()
+    ).await?;
+
+    channel.queue_bind(
+        queue.name().as_str(),
+        exchange.as_str(),
+        "",
+        QueueBindOptions::default(),
+        FieldTable::default()
+    ).await?;
+
+    channel.basic_consume(
+        queue.name().as_str(),
+        "",
+        BasicConsumeOptions {
+            no_ack: true,
+            ..BasicConsumeOptions::default()
+        },
+        FieldTable::default()
+    ).await
+}
+
+/// A request to cancel, identified by where its replies go along with its id,
+/// as ids are only unique to each caller.
+pub(super) type CancelTarget = (String, rpc::Id);
+
+/// Returns the request a control message cancels, if that's what it is. The
+/// caller is named in the params, or failing that by the message's reply_to,
+/// and cancellations which name neither are ignored.
+pub(super) fn cancel_target(delivery: &Delivery) -> Option<CancelTarget> {
+    match decode_requests(delivery, rpc::Strictness::Lenient, compression::DEFAULT_MAX_DECOMPRESSED) {
+        Ok(rpc::Payload::Single(Ok(request))) => {
+            let id = match request.cancel_target() {
+                Some(id) => id,
+                None => {
+                    log::warn!("Error: Unsupported control message {}", request);
+
+                    return None;
+                }
+            };
+
+            let reply_to = request.cancel_reply_to()
+                .map(ToString::to_string)
+                .or_else(|| delivery.properties.reply_to().as_ref().map(|reply_to| reply_to.to_string()));
+
+            if reply_to.is_none() {
+                log::warn!("Error: Cancellation {} doesn't say whose request it is", request);
+            }
+
+            Some((reply_to?, id))
+        },
+        _ => {
+            log::warn!("Error: Could not decode control message");
+
+            None
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::{Duration,SystemTime,UNIX_EPOCH};
+
+use lapin::BasicProperties;
+use lapin::message::Delivery;
+use lapin::types::{AMQPValue,FieldTable,LongString};
+
+use crate::AsyncResult;
+use crate::codec::{self,Codec};
+use crate::compression;
+use crate::context::RequestContext;
+use crate::rpc;
+
+/// Returns the codec matching the delivery's content type, if supported, as
+/// chosen by codec::for_content_type.
+pub fn delivery_codec(delivery: &Delivery, strictness: rpc::Strictness) -> Option<Arc<dyn Codec>> {
+    codec::for_content_type(delivery.properties.content_type().as_ref().map(|ct| ct.as_str()), strictness)
+}
+
+/// Carries a request's deadline in milliseconds since the UNIX epoch.
+pub const DEADLINE_HEADER : &str = "x-skein-deadline";
+
+/// Converts meta into AMQP headers, where each value is sent as a string.
+pub fn meta_headers(meta: &rpc::Meta) -> FieldTable {
+    let mut headers = FieldTable::default();
+
+    for (key, value) in meta {
+        headers.insert(key.as_str().into(), AMQPValue::LongString(LongString::from(value.as_str())));
+    }
+
+    headers
+}
+
+/// Extracts every one of the delivery's AMQP headers. Headers set by other
+/// publishers may use other types, so scalar values are converted to strings
+/// while anything else is skipped.
+pub fn delivery_headers(delivery: &Delivery) -> rpc::Meta {
+    let mut meta = rpc::Meta::new();
+
+    if let Some(headers) = delivery.properties.headers() {
+        for (key, value) in headers.inner() {
+            let value = match value {
+                AMQPValue::LongString(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+                AMQPValue::ShortString(s) => s.to_string(),
+                AMQPValue::Boolean(b) => b.to_string(),
+                AMQPValue::ShortShortInt(n) => n.to_string(),
+                AMQPValue::ShortShortUInt(n) => n.to_string(),
+                AMQPValue::ShortInt(n) => n.to_string(),
+                AMQPValue::ShortUInt(n) => n.to_string(),
+                AMQPValue::LongInt(n) => n.to_string(),
+                AMQPValue::LongUInt(n) => n.to_string(),
+                AMQPValue::LongLongInt(n) => n.to_string(),
+                AMQPValue::Float(n) => n.to_string(),
+                AMQPValue::Double(n) => n.to_string(),
+                _ => continue
+            };
+
+            meta.insert(key.to_string(), value);
+        }
+    }
+
+    meta
+}
+
+/// Extracts meta from the delivery's AMQP headers, as with delivery_headers
+/// but skipping those used internally.
+pub fn delivery_meta(delivery: &Delivery) -> rpc::Meta {
+    let mut meta = delivery_headers(delivery);
+
+    meta.remove(DEADLINE_HEADER);
+
+    meta
+}
+
+/// Describes how the delivery was received, apart from the queue it came
+/// from and the deadline for handling it, which are up to the consumer.
+pub fn delivery_context(delivery: &Delivery) -> RequestContext {
+    RequestContext {
+        delivery_tag: Some(delivery.delivery_tag),
+        redelivered: delivery.redelivered,
+        reply_to: delivery.properties.reply_to().as_ref().map(|reply_to| reply_to.to_string()).filter(|reply_to| !reply_to.is_empty()),
+        headers: delivery_headers(delivery),
+        timestamp: delivery.properties.timestamp().map(|timestamp| UNIX_EPOCH + Duration::from_secs(timestamp)),
+        queue: None,
+        received: Some(SystemTime::now()),
+        deadline: None
+    }
+}
+
+/// Merges the meta of several messages which share a single set of headers,
+/// with the first occurrence of any given key taking precedence.
+pub fn merge_meta<'a>(metas: impl IntoIterator<Item = &'a rpc::Meta>) -> rpc::Meta {
+    let mut merged = rpc::Meta::new();
+
+    for meta in metas {
+        for (key, value) in meta {
+            merged.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    merged
+}
+
+/// Adds meta to the headers, where any headers already present take
+/// precedence.
+pub fn with_meta(properties: BasicProperties, meta: &rpc::Meta) -> BasicProperties {
+    if meta.is_empty() {
+        return properties;
+    }
+
+    let mut headers = match properties.headers() {
+        Some(headers) => headers.clone(),
+        None => return properties.with_headers(meta_headers(meta))
+    };
+
+    for (key, value) in meta {
+        if !headers.contains_key(key) {
+            headers.insert(key.as_str().into(), AMQPValue::LongString(LongString::from(value.as_str())));
+        }
+    }
+
+    properties.with_headers(headers)
+}
+
+/// Stamps the deadline as a header for the worker, and as the message
+/// expiration so the broker can discard it if it's never picked up in time.
+pub fn with_deadline(properties: BasicProperties, deadline: Option<SystemTime>) -> BasicProperties {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return properties
+    };
+
+    let millis = deadline.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64;
+    let expiration = deadline.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO).as_millis();
+
+    let mut headers = properties.headers().clone().unwrap_or_default();
+
+    headers.insert(DEADLINE_HEADER.into(), AMQPValue::LongLongInt(millis));
+
+    properties.with_headers(headers).with_expiration(expiration.to_string().into())
+}
+
+/// Returns the deadline the delivery was stamped with, if any.
+pub fn delivery_deadline(delivery: &Delivery) -> Option<SystemTime> {
+    let millis = match delivery.properties.headers().as_ref()?.inner().get(DEADLINE_HEADER)? {
+        AMQPValue::LongLongInt(n) => u64::try_from(*n).ok()?,
+        AMQPValue::LongUInt(n) => *n as u64,
+        AMQPValue::LongInt(n) => u64::try_from(*n).ok()?,
+        AMQPValue::LongString(s) => String::from_utf8_lossy(s.as_bytes()).parse().ok()?,
+        _ => return None
+    };
+
+    Some(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Returns the delivery's payload with any declared content encoding removed,
+/// failing if it decompresses to more than max_decompressed bytes.
+pub fn delivery_data(delivery: &Delivery, max_decompressed: usize) -> AsyncResult<Cow<'_, [u8]>> {
+    compression::decompress(delivery.properties.content_encoding().as_ref().map(|ce| ce.as_str()), &delivery.data, max_decompressed)
+}
+
+fn undecodable_message(err: &(dyn std::error::Error + Send + Sync + 'static)) -> &'static str {
+    log::warn!("Error: Could not decode content {}", err);
+
+    if err.is::<compression::TooLarge>() {
+        "Parse error, content too large"
+    }
+    else {
+        "Parse error, invalid content encoding"
+    }
+}
+
+fn undecodable_content(err: Box<dyn std::error::Error + Send + Sync>) -> rpc::Response {
+    rpc::Response::new_error_without_id(
+        rpc::ErrorResponse::new(rpc::ErrorCode::ParseError, undecodable_message(err.as_ref()), None)
+    )
+}
+
+fn response_data(delivery: &Delivery, max_decompressed: usize) -> Result<Cow<'_, [u8]>, &'static str> {
+    delivery_data(delivery, max_decompressed).map_err(|err| undecodable_message(err.as_ref()))
+}
+
+pub(super) fn unsupported_content_type(delivery: &Delivery) -> rpc::Response {
+    log::warn!("Error: Unsupported content type {:?}", delivery.properties.content_type());
+
+    rpc::Response::new_error_without_id(
+        rpc::ErrorResponse::new(rpc::ErrorCode::ParseError, "Parse error, unsupported content type", None)
+    )
+}
+
+impl rpc::Request {
+    pub fn properties(&self, reply_to: &str) -> BasicProperties {
+        let properties = with_deadline(
+            with_meta(
+                BasicProperties::default().with_content_type(codec::JSON_CONTENT_TYPE.into()),
+                self.meta()
+            ),
+            self.deadline()
+        );
+
+        if self.reply_to() {
+            properties.with_reply_to(reply_to.into())
+        }
+        else {
+            properties
+        }
+    }
+}
+
+impl rpc::Batch<rpc::Request> {
+    /// As with a single request, though as the batch is sent with a single
+    /// set of headers the meta of all the requests is merged, and the
+    /// earliest deadline applies.
+    pub fn properties(&self, reply_to: &str) -> BasicProperties {
+        let properties = with_deadline(
+            with_meta(
+                BasicProperties::default().with_content_type(codec::JSON_CONTENT_TYPE.into()),
+                &merge_meta(self.iter().map(|r| r.meta()))
+            ),
+            self.iter().filter_map(|r| r.deadline()).min()
+        );
+
+        if self.iter().any(|r| r.reply_to()) {
+            properties.with_reply_to(reply_to.into())
+        }
+        else {
+            properties
+        }
+    }
+}
+
+impl TryFrom<&Delivery> for rpc::Request {
+    type Error = rpc::Response;
+
+    fn try_from(delivery: &Delivery) -> Result<Self, rpc::Response> {
+        let data = delivery_data(delivery, compression::DEFAULT_MAX_DECOMPRESSED).map_err(undecodable_content)?;
+
+        let mut request = match delivery_codec(delivery, rpc::Strictness::default()) {
+            Some(codec) => rpc::Request::decode_with(codec.as_ref(), &data)?,
+            None => return Err(unsupported_content_type(delivery))
+        };
+
+        *request.meta_mut() = delivery_meta(delivery);
+        request.set_deadline(delivery_deadline(delivery));
+
+        Ok(request)
+    }
+}
+
+/// Decodes a single request or batch of requests from a delivery, applying
+/// the given strictness to each. Each request receives the delivery's meta
+/// and deadline. Payloads decompressing to more than max_decompressed bytes
+/// are rejected as a parse error.
+#[allow(clippy::result_large_err)]
+pub fn decode_requests(delivery: &Delivery, strictness: rpc::Strictness, max_decompressed: usize) -> Result<rpc::Payload<Result<rpc::Request,rpc::Response>>, rpc::Response> {
+    let data = delivery_data(delivery, max_decompressed).map_err(undecodable_content)?;
+
+    let mut payload = match delivery_codec(delivery, strictness) {
+        Some(codec) => rpc::Payload::<Result<rpc::Request,rpc::Response>>::decode_with_strictness(codec.as_ref(), strictness, &data)?,
+        None => return Err(unsupported_content_type(delivery))
+    };
+
+    let meta = delivery_meta(delivery);
+    let deadline = delivery_deadline(delivery);
+
+    for request in payload.iter_mut().flatten() {
+        *request.meta_mut() = meta.clone();
+        request.set_deadline(deadline);
+    }
+
+    Ok(payload)
+}
+
+/// Decodes a single response or batch of responses from a delivery, applying
+/// the given strictness to each. Each response receives the delivery's meta.
+/// Payloads decompressing to more than max_decompressed bytes are rejected.
+pub fn decode_responses(delivery: &Delivery, strictness: rpc::Strictness, max_decompressed: usize) -> Result<rpc::Payload<rpc::Response>, &'static str> {
+    let data = response_data(delivery, max_decompressed)?;
+
+    let mut payload = match delivery_codec(delivery, strictness) {
+        Some(codec) => rpc::Payload::<rpc::Response>::decode_with_strictness(codec.as_ref(), strictness, &data)?,
+        None => {
+            log::warn!("Error: Unsupported content type {:?}", delivery.properties.content_type());
+
+            return Err("Parse error, unsupported content type");
+        }
+    };
+
+    let meta = delivery_meta(delivery);
+
+    for response in payload.iter_mut() {
+        *response.meta_mut() = meta.clone();
+    }
+
+    Ok(payload)
+}
+
+impl TryFrom<&Delivery> for rpc::Payload<Result<rpc::Request,rpc::Response>> {
+    type Error = rpc::Response;
+
+    fn try_from(delivery: &Delivery) -> Result<Self, rpc::Response> {
+        decode_requests(delivery, rpc::Strictness::default(), compression::DEFAULT_MAX_DECOMPRESSED)
+    }
+}
+
+impl TryFrom<&Delivery> for rpc::Response {
+    type Error = &'static str;
+
+    fn try_from(delivery: &Delivery) -> Result<Self, &'static str> {
+        let data = response_data(delivery, compression::DEFAULT_MAX_DECOMPRESSED)?;
+
+        let mut response = match delivery_codec(delivery, rpc::Strictness::default()) {
+            Some(codec) => rpc::Response::decode_with(codec.as_ref(), &data)?,
+            None => {
+                log::warn!("Error: Unsupported content type {:?}", delivery.properties.content_type());
+
+                return Err("Parse error, unsupported content type");
+            }
+        };
+
+        *response.meta_mut() = delivery_meta(delivery);
+
+        Ok(response)
+    }
+}
+
+impl TryFrom<&Delivery> for rpc::Payload<rpc::Response> {
+    type Error = &'static str;
+
+    fn try_from(delivery: &Delivery) -> Result<Self, &'static str> {
+        decode_responses(delivery, rpc::Strictness::default(), compression::DEFAULT_MAX_DECOMPRESSED)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lapin::acker::Acker;
+    use serde_json::json;
+
+    use super::*;
+
+    fn delivery(data: Vec<u8>, properties: BasicProperties) -> Delivery {
+        Delivery {
+            delivery_tag: 1,
+            exchange: "".into(),
+            routing_key: "test".into(),
+            redelivered: false,
+            properties,
+            data,
+            acker: Acker::default()
+        }
+    }
+
+    #[test]
+    fn test_request_meta() {
+        let request = rpc::Request::new(1, "echo", Some(json!([ "test" ])))
+            .with_meta("tenant", "example")
+            .with_meta("trace", "a1b2");
+
+        let delivery = delivery(request.encode().unwrap(), request.properties("reply"));
+
+        assert_eq!(rpc::Request::try_from(&delivery).unwrap(), request);
+
+        let payload = decode_requests(&delivery, rpc::Strictness::Strict, compression::DEFAULT_MAX_DECOMPRESSED).unwrap();
+
+        assert_eq!(payload.into_items(), vec![ Ok(request) ]);
+    }
+
+    #[test]
+    fn test_content_type() {
+        let request = rpc::Request::new(1, "echo", None);
+
+        for content_type in [ "application/json; charset=utf-8", "Application/JSON", "text/plain" ] {
+            let properties = request.properties("reply").with_content_type(content_type.into());
+            let delivery = delivery(request.encode().unwrap(), properties);
+
+            assert_eq!(decode_requests(&delivery, rpc::Strictness::Lenient, compression::DEFAULT_MAX_DECOMPRESSED).unwrap().into_items(), vec![ Ok(request.clone()) ]);
+        }
+
+        let properties = request.properties("reply").with_content_type("text/plain".into());
+        let delivery = delivery(request.encode().unwrap(), properties);
+        let err = decode_requests(&delivery, rpc::Strictness::Strict, compression::DEFAULT_MAX_DECOMPRESSED).unwrap_err();
+
+        assert_eq!(err.error().unwrap().message(), "Parse error, unsupported content type");
+    }
+
+    #[cfg(feature="gzip")]
+    #[test]
+    fn test_decompressed_limit() {
+        let request = rpc::Request::new(1, "echo", Some(json!([ "test".repeat(1000) ])));
+        let data = compression::Compression::Gzip.compress(&request.encode().unwrap()).unwrap();
+        let properties = request.properties("reply").with_content_encoding(compression::GZIP_ENCODING.into());
+
+        let delivery = delivery(data, properties);
+
+        assert!(decode_requests(&delivery, rpc::Strictness::Strict, compression::DEFAULT_MAX_DECOMPRESSED).is_ok());
+
+        let err = decode_requests(&delivery, rpc::Strictness::Strict, 1000).unwrap_err();
+
+        assert_eq!(err.error().unwrap().code(), rpc::ErrorCode::ParseError.code());
+        assert_eq!(decode_responses(&delivery, rpc::Strictness::Strict, 1000).unwrap_err(), "Parse error, content too large");
+    }
+
+    #[test]
+    fn test_delivery_context() {
+        let deadline = UNIX_EPOCH + Duration::from_millis(4_000_000_000_000);
+
+        let request = rpc::Request::new(1, "echo", None).with_meta("trace", "a1b2").with_deadline(deadline);
+        let properties = request.properties("reply").with_timestamp(1_700_000_000);
+
+        let mut delivery = delivery(request.encode().unwrap(), properties);
+
+        delivery.redelivered = true;
+
+        let context = delivery_context(&delivery);
+
+        assert_eq!(context.delivery_tag, Some(1));
+        assert!(context.redelivered);
+        assert_eq!(context.reply_to.as_deref(), Some("reply"));
+        assert_eq!(context.headers.get("trace").map(String::as_str), Some("a1b2"));
+        assert_eq!(context.headers.get(DEADLINE_HEADER).map(String::as_str), Some("4000000000000"));
+        assert_eq!(context.timestamp, Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+        assert!(context.received.is_some());
+
+        // Internal headers are left out of the meta.
+        assert!(!delivery_meta(&delivery).contains_key(DEADLINE_HEADER));
+    }
+
+    #[test]
+    fn test_request_deadline() {
+        let deadline = UNIX_EPOCH + Duration::from_millis(4_000_000_000_000);
+
+        let request = rpc::Request::new(1, "echo", None).with_meta("trace", "a1b2").with_deadline(deadline);
+        let properties = request.properties("reply");
+
+        assert!(properties.expiration().is_some());
+
+        let delivery = delivery(request.encode().unwrap(), properties);
+
+        assert_eq!(delivery_deadline(&delivery), Some(deadline));
+        assert_eq!(delivery_meta(&delivery), rpc::Meta::from([ ("trace".to_string(), "a1b2".to_string()) ]));
+        assert_eq!(rpc::Request::try_from(&delivery).unwrap().deadline(), Some(deadline));
+    }
+
+    #[test]
+    fn test_delivery_meta() {
+        let mut headers = FieldTable::default();
+
+        headers.insert("attempt".into(), AMQPValue::LongInt(3));
+        headers.insert("nested".into(), AMQPValue::FieldTable(FieldTable::default()));
+
+        let delivery = delivery(Vec::new(), BasicProperties::default().with_headers(headers));
+
+        assert_eq!(delivery_meta(&delivery), rpc::Meta::from([ ("attempt".to_string(), "3".to_string()) ]));
+    }
+
+    #[test]
+    fn test_batch_meta() {
+        let batch = rpc::Batch::new(vec![
+            rpc::Request::new(1, "echo", None).with_meta("trace", "first"),
+            rpc::Request::new(2, "echo", None).with_meta("trace", "second").with_meta("tenant", "example")
+        ]);
+
+        let delivery = delivery(codec::encode(&codec::Json, &batch).unwrap(), batch.properties("reply"));
+
+        let meta = delivery_meta(&delivery);
+
+        assert_eq!(meta.get("trace").map(String::as_str), Some("first"));
+        assert_eq!(meta.get("tenant").map(String::as_str), Some("example"));
+    }
+}
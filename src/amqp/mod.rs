@@ -1,6 +1,30 @@
 mod client;
 pub use client::Client;
 pub use client::ClientOptions;
+pub use client::ResultStream;
+pub use client::Subscription;
+
+mod control;
+pub use control::control_exchange;
+
+mod message;
+pub use message::delivery_codec;
+pub use message::delivery_context;
+pub use message::DEADLINE_HEADER;
+pub use message::delivery_data;
+pub use message::delivery_deadline;
+pub use message::delivery_headers;
+pub use message::delivery_meta;
+pub use message::merge_meta;
+pub use message::meta_headers;
+pub use message::with_deadline;
+pub use message::with_meta;
+pub use message::decode_requests;
+pub use message::decode_responses;
+
+mod notifier;
+pub use notifier::ReplyNotifier;
 
 mod worker;
+pub use worker::DEADLINE_EXCEEDED;
 pub use worker::Worker;
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lapin::{
+    BasicProperties,
+    options::BasicPublishOptions,
+    message::Delivery,
+    publisher_confirm::Confirmation,
+    Channel
+};
+use tokio::sync::watch;
+
+use crate::AsyncResult;
+use crate::codec::{self,Codec};
+use crate::compression::Compressor;
+use crate::rpc;
+use crate::sink::Notifier;
+use crate::subscription::Unreachable;
+
+use super::delivery_codec;
+use super::with_meta;
+
+fn encode(codec: &dyn Codec, compressor: &Compressor, notification: &rpc::Request) -> AsyncResult<(Vec<u8>,BasicProperties)> {
+    let (payload, content_encoding) = compressor.apply(codec::encode(codec, notification)?)?;
+
+    let properties = with_meta(
+        BasicProperties::default().with_content_type(codec.content_type().into()),
+        notification.meta()
+    );
+
+    match content_encoding {
+        Some(content_encoding) => Ok((payload, properties.with_content_encoding(content_encoding.into()))),
+        None => Ok((payload, properties))
+    }
+}
+
+/// Publishes notifications to a caller's reply queue, encoded the same way
+/// as the request they relate to.
+pub struct ReplyNotifier {
+    channel: Channel,
+    reply_to: String,
+    codec: Arc<dyn Codec>,
+    compressor: Compressor,
+    events: Option<Channel>,
+    released: watch::Sender<bool>
+}
+
+impl ReplyNotifier {
+    pub fn new(channel: Channel, reply_to: impl ToString, codec: Arc<dyn Codec>, compressor: Compressor) -> Self {
+        Self {
+            channel,
+            reply_to: reply_to.to_string(),
+            codec,
+            compressor,
+            events: None,
+            released: watch::channel(false).0
+        }
+    }
+
+    /// Returns a notifier for the sender of the delivery, or None if it has
+    /// no reply-to queue.
+    pub fn for_delivery(channel: &Channel, delivery: &Delivery, compressor: Compressor) -> Option<Self> {
+        let reply_to = delivery.properties.reply_to().as_ref()?.as_str();
+
+        if reply_to.is_empty() {
+            return None;
+        }
+
+        let codec = delivery_codec(delivery, rpc::Strictness::Lenient).unwrap_or_else(|| Arc::new(codec::Json));
+
+        Some(Self::new(channel.clone(), reply_to, codec, compressor))
+    }
+
+    /// Enables subscriptions, where events are published on the given
+    /// channel. This must be in confirm mode so events for a reply queue that
+    /// no longer exists are returned instead of silently dropped.
+    pub fn with_events(mut self, events: Channel) -> Self {
+        self.events = Some(events);
+
+        self
+    }
+
+    pub fn reply_to(&self) -> &str {
+        self.reply_to.as_str()
+    }
+
+    /// Lets subscription events flow, which is done once the reply to the
+    /// request has been published. Events go out on the events channel, so
+    /// AMQP may still deliver some ahead of the reply, and amqp::Client holds
+    /// those until it learns the subscription id.
+    pub fn release(&self) {
+        self.released.send_replace(true);
+    }
+}
+
+#[async_trait]
+impl Notifier for ReplyNotifier {
+    async fn notify(&self, notification: rpc::Request) -> AsyncResult<()> {
+        let (payload, properties) = encode(self.codec.as_ref(), &self.compressor, &notification)?;
+
+        self.channel.basic_publish(
+            "",
+            self.reply_to.as_str(),
+            Default::default(),
+            &payload,
+            properties
+        ).await?;
+
+        Ok(())
+    }
+
+    fn detach(&self) -> Option<Arc<dyn Notifier>> {
+        Some(Arc::new(EventNotifier {
+            channel: self.events.clone()?,
+            reply_to: self.reply_to.clone(),
+            codec: self.codec.clone(),
+            compressor: self.compressor,
+            released: self.released.subscribe()
+        }))
+    }
+}
+
+// Publishes subscription events, which are held until the reply that created
+// the subscription has been sent.
+struct EventNotifier {
+    channel: Channel,
+    reply_to: String,
+    codec: Arc<dyn Codec>,
+    compressor: Compressor,
+    released: watch::Receiver<bool>
+}
+
+#[async_trait]
+impl Notifier for EventNotifier {
+    async fn notify(&self, notification: rpc::Request) -> AsyncResult<()> {
+        // If the request is abandoned before replying, the caller never
+        // learned of the subscription.
+        if self.released.clone().wait_for(|released| *released).await.is_err() {
+            return Err(Box::new(Unreachable(self.reply_to.clone())));
+        }
+
+        let (payload, properties) = encode(self.codec.as_ref(), &self.compressor, &notification)?;
+
+        let confirmation = self.channel.basic_publish(
+            "",
+            self.reply_to.as_str(),
+            BasicPublishOptions {
+                mandatory: true,
+                ..BasicPublishOptions::default()
+            },
+            &payload,
+            properties
+        ).await?.await?;
+
+        match confirmation {
+            Confirmation::Ack(None) | Confirmation::NotRequested => Ok(()),
+            _ => Err(Box::new(Unreachable(self.reply_to.clone())))
+        }
+    }
+}
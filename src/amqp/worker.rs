@@ -1,6 +1,9 @@
-use futures::future::FutureExt;
+use futures::future::{pending,FutureExt};
 use futures::stream::StreamExt;
-use std::convert::TryFrom;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc,Mutex};
+use std::time::SystemTime;
 
 use lapin::{
     BasicProperties,
@@ -10,24 +13,51 @@ use lapin::{
     Channel,
     Connection,
     ConnectionProperties,
+    Consumer,
     Result as LapinResult
 };
+use tokio::sync::broadcast;
 use tokio::sync::mpsc;
-use tokio::task::JoinHandle;
+use tokio::task::{JoinHandle,JoinSet};
 use tokio::time::Duration;
 use tokio::time::Instant;
 use tokio::time::sleep;
 use tokio::time::timeout;
 
+use crate::codec;
+use crate::compression::{Compression,Compressor};
+use crate::context::RequestContext;
+use crate::openrpc;
 use crate::Responder;
 use crate::rpc;
+use crate::sink::{Notifier,Sink};
+
+use super::control::{cancel_target,control_consumer,CancelTarget};
+use super::decode_requests;
+use super::delivery_codec;
+use super::message::unsupported_content_type;
+use super::delivery_context;
+use super::merge_meta;
+use super::ReplyNotifier;
+use super::with_meta;
+
+// Cancellations are remembered in case they arrive before the request is
+// picked up, but only the most recent are kept.
+const CANCELLED_BACKLOG : usize = 1024;
+
+/// The error given to requests still being handled at the caller's deadline.
+pub const DEADLINE_EXCEEDED : rpc::ErrorCode = rpc::ErrorCode::ServerError(-32000);
 
 #[derive(Clone,Debug)]
 pub struct WorkerConfig {
     amqp_addr: String,
     queue_name: String,
     timeout_warning: Duration,
-    timeout_terminate: Duration
+    timeout_terminate: Duration,
+    strictness: rpc::Strictness,
+    compressor: Compressor,
+    concurrency: usize,
+    prefetch: Option<u16>
 }
 
 impl WorkerConfig {
@@ -39,17 +69,30 @@ impl WorkerConfig {
             amqp_addr,
             queue_name,
             timeout_warning: timeout_warning.unwrap_or_else(|| Duration::from_secs(30)),
-            timeout_terminate: timeout_terminate.unwrap_or_else(|| Duration::from_secs(300))
+            timeout_terminate: timeout_terminate.unwrap_or_else(|| Duration::from_secs(300)),
+            strictness: rpc::Strictness::default(),
+            compressor: Compressor::default(),
+            concurrency: 1,
+            prefetch: None
         }
     }
 
-    async fn channel(&self) -> LapinResult<Channel> {
+    // Returns the channel used to consume and reply to requests, along with
+    // one in confirm mode for subscription events.
+    async fn channels(&self) -> LapinResult<(Channel,Channel)> {
         let connection = Connection::connect(
             self.amqp_addr.as_str(),
             ConnectionProperties::default(),
         ).await?;
 
         let channel = connection.create_channel().await?;
+        let events = connection.create_channel().await?;
+
+        events.confirm_select(ConfirmSelectOptions { nowait: false }).await?;
+
+        if let Some(prefetch) = self.prefetch {
+            channel.basic_qos(prefetch, BasicQosOptions::default()).await?;
+        }
 
         channel.queue_declare(
             self.queue_name.as_str(),
@@ -63,14 +106,20 @@ impl WorkerConfig {
             FieldTable::default()
         ).await?;
 
-        Ok(channel)
+        Ok((channel, events))
     }
 }
 
+type Factory<C> = Arc<dyn Fn(&C) -> C + Send + Sync>;
+
 pub struct Worker<C> where C : Responder {
     context: C,
     terminated: mpsc::Receiver<()>,
-    config: WorkerConfig
+    config: WorkerConfig,
+    control: Option<Consumer>,
+    cancelled: Arc<Mutex<VecDeque<CancelTarget>>>,
+    events: Option<Channel>,
+    factory: Option<Factory<C>>
 }
 
 impl<C> Worker<C> where C : Responder {
@@ -81,12 +130,65 @@ impl<C> Worker<C> where C : Responder {
             Worker {
                 context,
                 terminated,
-                config: WorkerConfig::new(amqp_addr, queue_name, timeout_warning, timeout_terminate)
+                config: WorkerConfig::new(amqp_addr, queue_name, timeout_warning, timeout_terminate),
+                control: None,
+                cancelled: Arc::new(Mutex::new(VecDeque::new())),
+                events: None,
+                factory: None
             },
             terminator
         ))
     }
 
+    /// Sets how strictly incoming requests are checked against the JSON-RPC
+    /// 2.0 specification.
+    pub fn with_strictness(mut self, strictness: rpc::Strictness) -> Self {
+        self.config.strictness = strictness;
+
+        self
+    }
+
+    /// Compresses replies at or above the compression threshold.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.config.compressor.compression = Some(compression);
+
+        self
+    }
+
+    pub fn with_compression_threshold(mut self, threshold: usize) -> Self {
+        self.config.compressor.threshold = threshold;
+
+        self
+    }
+
+    /// Rejects requests which decompress to more than the given number of
+    /// bytes with a parse error.
+    pub fn with_max_decompressed(mut self, max_decompressed: usize) -> Self {
+        self.config.compressor.max_decompressed = max_decompressed;
+
+        self
+    }
+
+    /// Limits how many deliveries are taken from the queue ahead of those
+    /// acknowledged. By default there's no limit, or when handling deliveries
+    /// concurrently, it's the number handled at once.
+    pub fn with_prefetch(mut self, prefetch: u16) -> Self {
+        self.config.prefetch = Some(prefetch);
+
+        self
+    }
+
+    /// Handles up to the given number of deliveries at once, each with a
+    /// context produced by the factory from the worker's own. Contexts can
+    /// share state through an Arc, or be made fresh for each delivery.
+    pub fn with_context_factory<F>(mut self, concurrency: usize, factory: F) -> Self where F : Fn(&C) -> C + Send + Sync + 'static {
+        self.config.concurrency = concurrency.max(1);
+        self.config.prefetch = self.config.prefetch.or(Some(u16::try_from(self.config.concurrency).unwrap_or(u16::MAX)));
+        self.factory = Some(Arc::new(factory));
+
+        self
+    }
+
     pub fn context(&self) -> &C {
         &self.context
     }
@@ -100,29 +202,45 @@ impl<C> Worker<C> where C : Responder {
     }
 
     pub async fn handle_with_timeout(&mut self, channel: &Channel, delivery: &Delivery) {
-        // let mut warning = interval(self.config.timeout_warning);
-        let now = Instant::now();
-
-        match timeout(self.config.timeout_terminate, self.handle_rpc_delivery(&delivery)).await {
-            Ok(response) => {
-                self.try_reply_to(&channel, &delivery, &response).await;
-
-                channel.basic_ack(
-                    delivery.delivery_tag,
-                    BasicAckOptions::default()
-                ).map(|_| ()).await;
+        let mut handling = Handling {
+            context: &mut self.context,
+            config: &self.config,
+            events: self.events.as_ref(),
+            cancellations: Cancellations::Control(&mut self.control),
+            cancelled: &self.cancelled
+        };
 
-                log::trace!("RPC call processed in {:.2}s", now.elapsed().as_secs_f32());
-            },
-            Err(err) => {
-                log::error!("Timeout error when processing RPC call: {}", err);
+        handling.handle_with_timeout(channel, delivery).await;
+    }
 
-                channel.basic_nack(
-                    delivery.delivery_tag,
-                    BasicNackOptions::default()
-                ).map(|_| ()).await;
+    // Hands the delivery to a task of its own with a context from the
+    // factory, where cancellations are forwarded to it from the run loop.
+    fn spawn_handling(&self, tasks: &mut JoinSet<()>, factory: &Factory<C>, cancels: &broadcast::Sender<CancelTarget>, channel: &Channel, delivery: Delivery) {
+        let mut context = factory(&self.context);
+        let config = self.config.clone();
+        let events = self.events.clone();
+        let receiver = cancels.subscribe();
+        let cancelled = self.cancelled.clone();
+        let channel = channel.clone();
+
+        tasks.spawn(async move {
+            let mut handling = Handling {
+                context: &mut context,
+                config: &config,
+                events: events.as_ref(),
+                cancellations: Cancellations::Forwarded(receiver),
+                cancelled: &cancelled
+            };
+
+            handling.handle_with_timeout(&channel, &delivery).await;
+        });
+    }
 
-                log::trace!("RPC call failed in {:.2}s", now.elapsed().as_secs_f32());
+    // Waits for the deliveries still being handled to finish.
+    async fn drain(tasks: &mut JoinSet<()>) {
+        while let Some(result) = tasks.join_next().await {
+            if let Err(err) = result {
+                log::error!("Error: RPC call handling failed {}", err);
             }
         }
     }
@@ -130,11 +248,19 @@ impl<C> Worker<C> where C : Responder {
     pub fn run(mut self) -> JoinHandle<LapinResult<Self>> {
         let config = self.config.clone();
         let queue_name = self.config.queue_name.clone();
+        let factory = self.factory.clone();
 
         tokio::spawn(async move {
+            let mut tasks = JoinSet::new();
+            let (cancels, _) = broadcast::channel(CANCELLED_BACKLOG);
+
             loop {
-                match config.channel().await {
-                    Ok(channel) => {
+                match config.channels().await {
+                    Ok((channel, events)) => {
+                        // Subscribers from before a reconnection are cut off
+                        // when the old channel closes.
+                        self.events = Some(events);
+
                         match channel.basic_consume(
                             queue_name.as_str(),
                             "",
@@ -142,19 +268,33 @@ impl<C> Worker<C> where C : Responder {
                             FieldTable::default()
                         ).await {
                             Ok(mut consumer) => {
+                                self.control = match control_consumer(&channel, queue_name.as_str()).await {
+                                    Ok(control) => Some(control),
+                                    Err(err) => {
+                                        log::warn!("Error: Could not consume control messages, requests can't be cancelled {}", err);
+
+                                        None
+                                    }
+                                };
+
                                 loop {
                                     tokio::select!(
                                         _ = self.terminated.recv() => {
                                             log::trace!("Worker terminated by request.");
 
+                                            Self::drain(&mut tasks).await;
+
                                             return Ok(self);
                                         },
-                                        message = consumer.next() => {
+                                        message = consumer.next(), if tasks.len() < config.concurrency => {
                                             match message {
                                                 Some(Ok(delivery)) => {
                                                     log::trace!("Dispatching RPC call");
 
-                                                    self.handle_with_timeout(&channel, &delivery).await;
+                                                    match &factory {
+                                                        Some(factory) => self.spawn_handling(&mut tasks, factory, &cancels, &channel, delivery),
+                                                        None => self.handle_with_timeout(&channel, &delivery).await
+                                                    }
                                                 },
                                                 Some(Err(err)) => {
                                                     log::error!("Error: {:?}", err);
@@ -168,14 +308,40 @@ impl<C> Worker<C> where C : Responder {
                                                     break;
                                                 }
                                             }
+                                        },
+                                        Some(result) = tasks.join_next(), if !tasks.is_empty() => {
+                                            if let Err(err) = result {
+                                                log::error!("Error: RPC call handling failed {}", err);
+                                            }
+                                        },
+                                        message = next_control(&mut self.control) => {
+                                            if let Some(target) = control_message(&mut self.control, message) {
+                                                remember_cancelled(&mut self.cancelled.lock().unwrap(), target.clone());
+
+                                                // Only deliveries being handled concurrently
+                                                // are listening, so there may be none.
+                                                cancels.send(target).ok();
+                                            }
                                         }
                                     )
                                 }
+
+                                // Deliveries still being handled were taken on this
+                                // channel, so can't be acknowledged or replied to on
+                                // the next. They're abandoned, and closing the channel
+                                // returns them to the queue for another attempt.
+                                tasks.shutdown().await;
+
+                                channel.close(200, "Reconnecting").await.ok();
                             },
                             Err(err) => {
                                 log::error!("Error connecting consumer: {}", err);
 
-                                sleep(Duration::from_secs(1)).await;
+                                if self.wait_for_retry().await {
+                                    Self::drain(&mut tasks).await;
+
+                                    return Ok(self);
+                                }
 
                                 log::warn!("AMQP consumer reconnecting.");
                             }
@@ -184,29 +350,133 @@ impl<C> Worker<C> where C : Responder {
                     Err(err) => {
                         log::error!("Error connecting channel: {}", err);
 
-                        sleep(Duration::from_secs(1)).await;
+                        if self.wait_for_retry().await {
+                            Self::drain(&mut tasks).await;
+
+                            return Ok(self);
+                        }
                     }
                 }
             }
         })
     }
 
-    async fn try_reply_to(&self, channel: &Channel, delivery: &Delivery, response: &rpc::Response) {
+    // Pauses before a reconnection attempt, returning true if the worker was
+    // terminated in the meantime.
+    async fn wait_for_retry(&mut self) -> bool {
+        tokio::select!(
+            _ = self.terminated.recv() => {
+                log::trace!("Worker terminated by request.");
+
+                true
+            },
+            _ = sleep(Duration::from_secs(1)) => false
+        )
+    }
+}
+
+impl<C> Worker<C> where C : Responder + Clone {
+    /// Handles up to the given number of deliveries at once, each with its
+    /// own clone of the context. A context holding its state in an Arc, such
+    /// as a Router, shares that state between them.
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        self.with_context_factory(concurrency, C::clone)
+    }
+}
+
+// What's needed to handle a delivery, borrowed from the worker or, when
+// handling deliveries concurrently, owned by the task doing so.
+struct Handling<'a,C> where C : Responder {
+    context: &'a mut C,
+    config: &'a WorkerConfig,
+    events: Option<&'a Channel>,
+    cancellations: Cancellations<'a>,
+    cancelled: &'a Mutex<VecDeque<CancelTarget>>
+}
+
+impl<C> Handling<'_,C> where C : Responder {
+    async fn handle_with_timeout(&mut self, channel: &Channel, delivery: &Delivery) {
+        // let mut warning = interval(self.config.timeout_warning);
+        let now = Instant::now();
+
+        let notifier = ReplyNotifier::for_delivery(channel, delivery, self.config.compressor).map(|notifier| {
+            match self.events {
+                Some(events) => Arc::new(notifier.with_events(events.clone())),
+                None => Arc::new(notifier)
+            }
+        });
+
+        match timeout(self.config.timeout_terminate, self.handle_rpc_delivery(delivery, notifier.clone())).await {
+            Ok(response) => {
+                if let Some(response) = response {
+                    self.try_reply_to(channel, delivery, &response).await;
+                }
+
+                if let Some(notifier) = notifier {
+                    notifier.release();
+                }
+
+                channel.basic_ack(
+                    delivery.delivery_tag,
+                    BasicAckOptions::default()
+                ).map(|_| ()).await;
+
+                let elapsed = now.elapsed();
+
+                if elapsed > self.config.timeout_warning {
+                    log::warn!("RPC call took {:.2}s to process", elapsed.as_secs_f32());
+                }
+
+                log::trace!("RPC call processed in {:.2}s", elapsed.as_secs_f32());
+            },
+            Err(err) => {
+                log::error!("Timeout error when processing RPC call: {}", err);
+
+                channel.basic_nack(
+                    delivery.delivery_tag,
+                    BasicNackOptions::default()
+                ).map(|_| ()).await;
+
+                log::trace!("RPC call failed in {:.2}s", now.elapsed().as_secs_f32());
+            }
+        }
+    }
+
+    async fn try_reply_to(&self, channel: &Channel, delivery: &Delivery, response: &rpc::Payload<rpc::Response>) {
         if let Some(reply_to) = delivery.properties.reply_to() {
             let reply_to = reply_to.as_str();
 
             if !reply_to.is_empty() {
-                match serde_json::to_string(response) {
-                    Ok(str) => {
-                        let payload = str.as_bytes();
+                // Reply in the same encoding as the request, falling back to
+                // JSON if the request couldn't be decoded.
+                let codec = delivery_codec(delivery, rpc::Strictness::Lenient).unwrap_or_else(|| Arc::new(codec::Json));
+
+                match codec::encode(codec.as_ref(), response).and_then(|payload| self.config.compressor.apply(payload)) {
+                    Ok((payload, content_encoding)) => {
+                        let properties = with_meta(
+                            BasicProperties::default().with_content_type(codec.content_type().into()),
+                            &merge_meta(response.iter().map(|r| r.meta()))
+                        );
+
+                        let properties = match content_encoding {
+                            Some(content_encoding) => properties.with_content_encoding(content_encoding.into()),
+                            None => properties
+                        };
+
+                        // Lets the caller tie errors without an id to what
+                        // was sent.
+                        let properties = match delivery.properties.correlation_id() {
+                            Some(correlation_id) => properties.with_correlation_id(correlation_id.clone()),
+                            None => properties
+                        };
 
                         // FIX: Warn on transmission error
                         if let Err(err) = channel.basic_publish(
                             "",
                             reply_to,
                             Default::default(),
-                            payload,
-                            BasicProperties::default().with_content_type("application/json".into())
+                            &payload,
+                            properties
                         ).await {
                             log::warn!("Error: Could not publish reply {:?}", err);
                         }
@@ -222,34 +492,287 @@ impl<C> Worker<C> where C : Responder {
         }
     }
 
-    async fn handle_rpc_delivery(&mut self, delivery: &Delivery) -> rpc::Response {
-        match rpc::Request::try_from(delivery) {
-            Ok(request) => {
-                log::trace!("Request received: {}", request.id());
+    // Returns None for notifications, which never receive a reply, and for
+    // requests whose deadline has passed as the caller is no longer waiting.
+    async fn handle_rpc_request(&mut self, request: rpc::Request, context: &RequestContext, notifier: Option<&Arc<dyn Notifier>>) -> Option<rpc::Response> {
+        log::trace!("Request received: {}", request);
 
-                let request = self.context.prepare_request(request);
+        if request.is_expired() {
+            log::warn!("Error: Request {} expired before processing, skipping", request);
 
-                match self.context.respond(&request).await {
-                    Ok(result) => {
-                        rpc::Response::result_for(&request, result)
-                    },
-                    Err(err) => {
-                        log::warn!("Error: Internal processing error {:?}", err);
+            return None;
+        }
+
+        // Handling is abandoned at the caller's deadline, answering with an
+        // error, or at the worker's, where timeout_terminate gives up on the
+        // delivery, whichever comes first.
+        let deadline = request.deadline().into_iter().chain(context.deadline).min();
+        let request = request.with_context(RequestContext { deadline, ..context.clone() });
+
+        let request = self.context.prepare_request(request);
+
+        let context = &mut *self.context;
+
+        let sink = match notifier {
+            Some(notifier) => Sink::new(&request, notifier.clone()),
+            None => Sink::discard()
+        };
+
+        let respond = async {
+            if request.method() == openrpc::DISCOVER_METHOD {
+                if let Some(document) = context.describe() {
+                    return serde_json::to_value(document).map_err(|e| e.into());
+                }
+            }
+
+            context.respond_streaming(&request, &sink).await
+        };
+
+        let respond = async {
+            match request.remaining() {
+                Some(remaining) => {
+                    match timeout(remaining, respond).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            log::warn!("Error: Request {} passed its deadline", request);
+
+                            Err(rpc::ErrorResponse::new(DEADLINE_EXCEEDED, "Deadline exceeded", None).into())
+                        }
+                    }
+                },
+                None => respond.await
+            }
+        };
+
+        // Only requests with somewhere to reply can be cancelled, as the
+        // caller is known by that.
+        let target = match (request.context().reply_to.as_ref(), request.id()) {
+            (Some(reply_to), Some(id)) => Some((reply_to.clone(), id.clone())),
+            _ => None
+        };
+
+        let result = match target {
+            Some(target) => {
+                let cancelled = if take_cancelled(self.cancelled, &target) {
+                    None
+                }
+                else {
+                    until_cancelled(respond, &mut self.cancellations, self.cancelled, &target).await
+                };
+
+                match cancelled {
+                    Some(result) => result,
+                    None => {
+                        log::warn!("Error: Request {} cancelled by caller", request);
+
+                        return Some(rpc::Response::error_for(&request, rpc::ErrorCode::RequestCancelled, rpc::ErrorCode::RequestCancelled.message(), None));
+                    }
+                }
+            },
+            None => respond.await
+        };
+
+        let response = match result {
+            Ok(result) => {
+                rpc::Response::result_for(&request, result)
+            },
+            Err(err) => {
+                rpc::Response::error_from(&request, err)
+            }
+        };
+
+        if request.is_notification() {
+            None
+        }
+        else {
+            Some(response)
+        }
+    }
+
+    async fn handle_rpc_delivery(&mut self, delivery: &Delivery, notifier: Option<Arc<ReplyNotifier>>) -> Option<rpc::Payload<rpc::Response>> {
+        let notifier = notifier.map(|notifier| notifier as Arc<dyn Notifier>);
+
+        let context = RequestContext {
+            queue: Some(self.config.queue_name.clone()),
+            deadline: Some(SystemTime::now() + self.config.timeout_terminate),
+            ..delivery_context(delivery)
+        };
 
-                        rpc::Response::error_for(&request, -32603, "Internal processing error", None)
+        // Strictness is applied here rather than when decoding so rejected
+        // notifications can go unanswered.
+        if delivery_codec(delivery, self.config.strictness).is_none() {
+            return Some(rpc::Payload::Single(unsupported_content_type(delivery)));
+        }
+
+        match decode_requests(delivery, rpc::Strictness::Lenient, self.config.compressor.max_decompressed) {
+            Ok(rpc::Payload::Single(item)) => {
+                match check_strictness(item, self.config.strictness)? {
+                    Ok(request) => self.handle_rpc_request(request, &context, notifier.as_ref()).await.map(rpc::Payload::Single),
+                    Err(response) => Some(rpc::Payload::Single(response))
+                }
+            },
+            Ok(rpc::Payload::Batch(batch)) => {
+                log::trace!("Batch received: {} request(s)", batch.len());
+
+                let mut responses = rpc::Batch::new(Vec::with_capacity(batch.len()));
+
+                for item in batch.into_iter().filter_map(|item| check_strictness(item, self.config.strictness)) {
+                    match item {
+                        Ok(request) => {
+                            if let Some(response) = self.handle_rpc_request(request, &context, notifier.as_ref()).await {
+                                responses.push(response);
+                            }
+                        },
+                        Err(response) => responses.push(response)
                     }
                 }
+
+                // A batch made up entirely of notifications gets no reply.
+                if responses.is_empty() {
+                    None
+                }
+                else {
+                    Some(rpc::Payload::Batch(responses))
+                }
             },
-            Err(response) => response
+            Err(response) => Some(rpc::Payload::Single(response))
+        }
+    }
+}
+
+// Checks a decoded request against the strictness, returning None if it was
+// a notification that was rejected, as notifications are never answered.
+fn check_strictness(item: Result<rpc::Request,rpc::Response>, strictness: rpc::Strictness) -> Option<Result<rpc::Request,rpc::Response>> {
+    match item {
+        Ok(request) if request.is_notification() => {
+            match request.check_strictness(strictness) {
+                Ok(request) => Some(Ok(request)),
+                Err(_) => {
+                    log::warn!("Error: Rejected notification left unanswered");
+
+                    None
+                }
+            }
+        },
+        Ok(request) => Some(request.check_strictness(strictness)),
+        Err(response) => Some(Err(response))
+    }
+}
+
+// Waits for the next control message, or forever if there's no control
+// consumer.
+async fn next_control(control: &mut Option<Consumer>) -> Option<LapinResult<Delivery>> {
+    match control {
+        Some(consumer) => consumer.next().await,
+        None => pending().await
+    }
+}
+
+// Returns the request to cancel, if any. A failed control consumer is
+// dropped, leaving requests uncancellable until the worker reconnects.
+fn control_message(control: &mut Option<Consumer>, message: Option<LapinResult<Delivery>>) -> Option<CancelTarget> {
+    match message {
+        Some(Ok(delivery)) => cancel_target(&delivery),
+        Some(Err(err)) => {
+            log::warn!("Error: Control consumer failed {:?}", err);
+
+            *control = None;
+
+            None
+        },
+        None => {
+            *control = None;
+
+            None
+        }
+    }
+}
+
+fn remember_cancelled(cancelled: &mut VecDeque<CancelTarget>, target: CancelTarget) {
+    if cancelled.len() >= CANCELLED_BACKLOG {
+        cancelled.pop_front();
+    }
+
+    cancelled.push_back(target);
+}
+
+// Returns true, forgetting it, if the request was cancelled before it was
+// picked up.
+fn take_cancelled(cancelled: &Mutex<VecDeque<CancelTarget>>, target: &CancelTarget) -> bool {
+    let mut cancelled = cancelled.lock().unwrap();
+
+    match cancelled.iter().position(|c| c == target) {
+        Some(index) => {
+            cancelled.remove(index);
+
+            true
+        },
+        None => false
+    }
+}
+
+// Where a request being handled learns of cancellations, either read from the
+// control consumer directly or, when handling deliveries concurrently,
+// forwarded by the run loop which owns it.
+enum Cancellations<'a> {
+    Control(&'a mut Option<Consumer>),
+    Forwarded(broadcast::Receiver<CancelTarget>)
+}
+
+impl Cancellations<'_> {
+    // Waits until the given request is cancelled, which may be never.
+    async fn wait_for(&mut self, target: &CancelTarget, cancelled: &Mutex<VecDeque<CancelTarget>>) {
+        match self {
+            Self::Control(control) => {
+                loop {
+                    let message = next_control(control).await;
+
+                    match control_message(control, message) {
+                        Some(other) if &other == target => return,
+                        Some(other) => remember_cancelled(&mut cancelled.lock().unwrap(), other),
+                        None => ()
+                    }
+                }
+            },
+            Self::Forwarded(receiver) => {
+                loop {
+                    match receiver.recv().await {
+                        // The run loop remembered this along with the rest.
+                        Ok(other) if &other == target => {
+                            take_cancelled(cancelled, target);
+
+                            return;
+                        },
+                        Ok(_) => (),
+                        // Any missed may be among those remembered.
+                        Err(broadcast::error::RecvError::Lagged(_)) => {
+                            if take_cancelled(cancelled, target) {
+                                return;
+                            }
+                        },
+                        Err(broadcast::error::RecvError::Closed) => pending().await
+                    }
+                }
+            }
         }
     }
 }
 
+// Runs the handler until it completes, returning its result, or until the
+// request is cancelled, returning None and dropping the handler mid-flight.
+async fn until_cancelled<T>(respond: impl Future<Output=T>, cancellations: &mut Cancellations<'_>, cancelled: &Mutex<VecDeque<CancelTarget>>, target: &CancelTarget) -> Option<T> {
+    tokio::select!(
+        result = respond => Some(result),
+        _ = cancellations.wait_for(target, cancelled) => None
+    )
+}
+
 #[cfg(test)]
 mod test {
     use std::env;
 
     use async_trait::async_trait;
+    use lapin::acker::Acker;
     use serde_json::json;
     use serde_json::Value;
 
@@ -294,4 +817,203 @@ mod test {
 
         assert_eq!(worker.queue_name(), "test");
     }
+
+    #[tokio::test]
+    async fn test_strict_notifications() {
+        let (worker, _) = Worker::new(ContextExample { id: 1, terminated: false }, "amqp://localhost:5672/%2f", "test", None, None).unwrap();
+        let mut worker = worker.with_strictness(rpc::Strictness::Strict);
+        let cancelled = Mutex::new(VecDeque::new());
+        let mut control = None;
+
+        let mut handling = Handling {
+            context: &mut worker.context,
+            config: &worker.config,
+            events: None,
+            cancellations: Cancellations::Control(&mut control),
+            cancelled: &cancelled
+        };
+
+        let delivery = |data: &str| {
+            Delivery {
+                delivery_tag: 1,
+                exchange: "".into(),
+                routing_key: "test".into(),
+                redelivered: false,
+                properties: BasicProperties::default(),
+                data: data.as_bytes().to_vec(),
+                acker: Acker::default()
+            }
+        };
+
+        // Rejected notifications get no reply, alone or in a batch.
+        let notification = "{\"jsonrpc\":\"2.0\",\"method\":\"echo\",\"trace\":\"a1b2\"}";
+        let request = "{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"echo\",\"trace\":\"a1b2\"}";
+
+        assert_eq!(handling.handle_rpc_delivery(&delivery(notification), None).await, None);
+
+        let response = handling.handle_rpc_delivery(&delivery(request), None).await.unwrap().into_items().remove(0);
+
+        assert_eq!(response.id(), &rpc::Id::from(2));
+        assert!(response.error().unwrap().is_invalid_request());
+
+        let batch = format!("[{},{}]", notification, request);
+        let responses = handling.handle_rpc_delivery(&delivery(&batch), None).await.unwrap().into_items();
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id(), &rpc::Id::from(2));
+
+        let batch = format!("[{},{}]", notification, notification);
+
+        assert_eq!(handling.handle_rpc_delivery(&delivery(&batch), None).await, None);
+    }
+
+    #[derive(Clone)]
+    struct Sleeper;
+
+    #[async_trait]
+    impl Responder for Sleeper {
+        async fn respond(&mut self, _request: &rpc::Request) -> AsyncResult<Value> {
+            sleep(Duration::from_millis(200)).await;
+
+            Ok(json!("Slept"))
+        }
+    }
+
+    #[test]
+    fn test_concurrency_prefetch() {
+        let (worker, _) = Worker::new(Sleeper, "amqp://localhost:5672/%2f", "test", None, None).unwrap();
+
+        assert_eq!(worker.config.prefetch, None);
+
+        let worker = worker.with_concurrency(4);
+
+        assert_eq!(worker.config.concurrency, 4);
+        assert_eq!(worker.config.prefetch, Some(4));
+
+        let (worker, _) = Worker::new(Sleeper, "amqp://localhost:5672/%2f", "test", None, None).unwrap();
+        let worker = worker.with_prefetch(10).with_concurrency(0);
+
+        assert_eq!(worker.config.concurrency, 1);
+        assert_eq!(worker.config.prefetch, Some(10));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_handling() {
+        let (worker, _) = Worker::new(Sleeper, "amqp://localhost:5672/%2f", "test", None, None).unwrap();
+        let worker = worker.with_concurrency(2);
+        let factory = worker.factory.clone().unwrap();
+        let (cancels, _) = broadcast::channel(4);
+
+        // As the run loop does for each delivery, handle each request with a
+        // context of its own from the factory.
+        let handle = |id: u64| {
+            let mut context = factory(&worker.context);
+            let config = worker.config.clone();
+            let cancelled = worker.cancelled.clone();
+            let receiver = cancels.subscribe();
+
+            tokio::spawn(async move {
+                let mut handling = Handling {
+                    context: &mut context,
+                    config: &config,
+                    events: None,
+                    cancellations: Cancellations::Forwarded(receiver),
+                    cancelled: &cancelled
+                };
+
+                let request_context = RequestContext { reply_to: Some("a".to_string()), ..RequestContext::default() };
+
+                handling.handle_rpc_request(rpc::Request::new(id, "sleep", None), &request_context, None).await
+            })
+        };
+
+        let start = Instant::now();
+        let (first, second) = tokio::join!(handle(1), handle(2));
+
+        assert_eq!(first.unwrap().unwrap().result(), Some(&json!("Slept")));
+        assert_eq!(second.unwrap().unwrap().result(), Some(&json!("Slept")));
+
+        // Both finish in about the time one takes.
+        assert!(start.elapsed() < Duration::from_millis(350));
+    }
+
+    #[tokio::test]
+    async fn test_caller_deadline() {
+        let (mut worker, _) = Worker::new(Sleeper, "amqp://localhost:5672/%2f", "test", None, None).unwrap();
+        let cancelled = Mutex::new(VecDeque::new());
+        let mut control = None;
+
+        let mut handling = Handling {
+            context: &mut worker.context,
+            config: &worker.config,
+            events: None,
+            cancellations: Cancellations::Control(&mut control),
+            cancelled: &cancelled
+        };
+
+        let start = Instant::now();
+        let request = rpc::Request::new(1, "sleep", None).with_timeout(Duration::from_millis(50));
+        let response = handling.handle_rpc_request(request, &RequestContext::default(), None).await.unwrap();
+
+        assert_eq!(response.error().unwrap().error_code(), DEADLINE_EXCEEDED);
+        assert!(start.elapsed() < Duration::from_millis(150));
+
+        let request = rpc::Request::new(2, "sleep", None).with_timeout(Duration::from_secs(5));
+        let response = handling.handle_rpc_request(request, &RequestContext::default(), None).await.unwrap();
+
+        assert_eq!(response.result(), Some(&json!("Slept")));
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_cancellations() {
+        let cancelled = Mutex::new(VecDeque::new());
+        let (cancels, receiver) = broadcast::channel(4);
+        let mut cancellations = Cancellations::Forwarded(receiver);
+
+        remember_cancelled(&mut cancelled.lock().unwrap(), target("a", 1));
+
+        assert!(take_cancelled(&cancelled, &target("a", 1)));
+        assert!(!take_cancelled(&cancelled, &target("a", 1)));
+
+        cancels.send(target("a", 2)).unwrap();
+        cancels.send(target("a", 3)).unwrap();
+
+        let result = until_cancelled(pending::<()>(), &mut cancellations, &cancelled, &target("a", 3)).await;
+
+        assert_eq!(result, None);
+
+        let result = until_cancelled(async { 4 }, &mut cancellations, &cancelled, &target("a", 4)).await;
+
+        assert_eq!(result, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_cancellations_from_other_callers() {
+        let cancelled = Mutex::new(VecDeque::new());
+        let (cancels, receiver) = broadcast::channel(4);
+        let mut cancellations = Cancellations::Forwarded(receiver);
+
+        // Another caller using the same id doesn't cancel this request,
+        // whether it was cancelled before or while being handled.
+        remember_cancelled(&mut cancelled.lock().unwrap(), target("b", 1));
+
+        assert!(!take_cancelled(&cancelled, &target("a", 1)));
+
+        cancels.send(target("b", 1)).unwrap();
+
+        let result = until_cancelled(tokio::time::sleep(Duration::from_millis(50)), &mut cancellations, &cancelled, &target("a", 1)).await;
+
+        assert_eq!(result, Some(()));
+
+        cancels.send(target("b", 1)).unwrap();
+        cancels.send(target("a", 1)).unwrap();
+
+        let result = until_cancelled(pending::<()>(), &mut cancellations, &cancelled, &target("a", 1)).await;
+
+        assert_eq!(result, None);
+    }
+
+    fn target(reply_to: &str, id: u64) -> CancelTarget {
+        (reply_to.to_string(), rpc::Id::from(id))
+    }
 }
@@ -7,14 +7,20 @@ use std::time::Instant;
 
 use clap::Parser;
 use dotenv::dotenv;
+use futures::stream::StreamExt;
 use serde_json::json;
+use tokio::sync::mpsc::unbounded_channel;
 use tokio::time::sleep;
 
 use skein_rpc::AsyncResult;
 use skein_rpc::Client;
+use skein_rpc::codec;
+use skein_rpc::Compression;
 use skein_rpc::amqp::Client as AMQPClient;
 use skein_rpc::amqp::ClientOptions as AMQPClientOptions;
 use skein_rpc::logging;
+use skein_rpc::openrpc;
+use skein_rpc::rpc;
 
 #[derive(Parser)]
 #[clap(version = env!("CARGO_PKG_VERSION"))]
@@ -45,7 +51,28 @@ struct Program {
     timeout : Duration,
     #[clap(long)]
     noreply : bool,
-    method : String,
+    #[clap(long)]
+    notify : bool,
+    #[clap(long)]
+    stream : bool,
+    #[clap(long)]
+    progress : bool,
+    #[clap(long)]
+    subscribe : bool,
+    #[clap(long,default_value="json",possible_values=["json","msgpack","cbor"])]
+    encoding : String,
+    #[clap(long)]
+    strict : bool,
+    #[clap(long,possible_values=["gzip","zstd"])]
+    compression : Option<Compression>,
+    #[clap(long)]
+    compression_threshold : Option<usize>,
+    #[clap(long)]
+    discover : bool,
+    #[clap(long,multiple_occurrences=true,parse(try_from_str=Self::try_into_meta))]
+    meta : Vec<(String,String)>,
+    #[clap(required_unless_present="discover")]
+    method : Option<String>,
     #[clap(multiple=true)]
     args : Vec<String>
 }
@@ -54,6 +81,13 @@ impl Program {
     fn try_into_duration(s: &str) -> Result<Duration, ParseFloatError> {
         s.parse().map(Duration::from_secs_f32)
     }
+
+    fn try_into_meta(s: &str) -> Result<(String,String), String> {
+        match s.split_once('=') {
+            Some((key, value)) => Ok((key.to_string(), value.to_string())),
+            None => Err(format!("expected key=value, not {:?}", s))
+        }
+    }
 }
 
 #[tokio::main(flavor = "multi_thread")]
@@ -77,12 +111,76 @@ async fn main() -> AsyncResult<()> {
         program.ident.unwrap_or_else(|| "amqp-client".to_string())
     ).with_timeout(program.timeout);
 
+    let options = match program.encoding.as_str() {
+        #[cfg(feature="msgpack")]
+        "msgpack" => options.with_codec(codec::MessagePack),
+        #[cfg(feature="cbor")]
+        "cbor" => options.with_codec(codec::Cbor),
+        _ => options.with_codec(codec::Json)
+    };
+
+    let options = if program.strict {
+        options.with_strictness(rpc::Strictness::Strict)
+    }
+    else {
+        options
+    };
+
+    let options = program.meta.iter().fold(options, |options, (key, value)| options.with_meta(key, value));
+
+    let options = match program.compression {
+        Some(compression) => options.with_compression(compression),
+        None => options
+    };
+
+    let options = match program.compression_threshold {
+        Some(threshold) => options.with_compression_threshold(threshold),
+        None => options
+    };
+
     // skein_test
 
     let client = AMQPClient::new(options).await?;
-    let method = program.method;
+
+    let mut errors = client.subscribe_errors();
+
+    tokio::spawn(async move {
+        while let Ok(response) = errors.recv().await {
+            log::error!("Error received for unknown request: {}", response);
+        }
+    });
+
+    if program.discover {
+        let document = client.rpc_request(openrpc::DISCOVER_METHOD, None).await?;
+
+        println!("{}", serde_json::to_string_pretty(&document)?);
+
+        client.close();
+        client.into_handle().await?;
+
+        return Ok(());
+    }
+
+    let method = program.method.unwrap_or_default();
     let params = Some(json!(program.args));
 
+    if program.subscribe {
+        let mut subscription = client.subscribe(method.as_str(), params).await?;
+
+        log::info!("Subscribed as {}", subscription.id());
+
+        while let Some(event) = subscription.next().await {
+            println!("{}", event);
+        }
+
+        log::info!("Subscription ended");
+
+        client.close();
+        client.into_handle().await?;
+
+        return Ok(());
+    }
+
     let now = Instant::now();
 
     let repeat = program.repeat;
@@ -91,7 +189,66 @@ async fn main() -> AsyncResult<()> {
 
     let mut receipt_log = program.receipt_log.map(|path| File::create(path.as_str()).unwrap());
 
-    if program.noreply {
+    if program.notify {
+        for i in 0..repeat {
+            let params = if program.sequencer {
+                Some(json!(i + 1))
+            }
+            else {
+                params.clone()
+            };
+
+            match client.rpc_notify(method.as_str(), params.clone()).await {
+                Ok(()) => {
+                    completed += 1;
+
+                    log::debug!("Sent {}/{}", completed, repeat);
+                },
+                Err(err) => {
+                    log::error!("Error with notification: {}", err);
+                }
+            }
+
+            sleep(program.repeat_delay).await;
+        }
+    }
+    else if program.stream {
+        for i in 0..repeat {
+            let params = if program.sequencer {
+                Some(json!(i + 1))
+            }
+            else {
+                params.clone()
+            };
+
+            match client.rpc_stream(method.as_str(), params.clone()) {
+                Ok(mut stream) => {
+                    while let Some(item) = stream.next().await {
+                        match item {
+                            Ok(value) => {
+                                if !program.silent {
+                                    println!("{}", value);
+                                }
+                            },
+                            Err(err) => {
+                                log::error!("Error streaming request: {}", err);
+                            }
+                        }
+                    }
+
+                    completed += 1;
+
+                    log::debug!("Sent {}/{}", completed, repeat);
+                },
+                Err(err) => {
+                    log::error!("Error sending request: {}", err);
+                }
+            }
+
+            sleep(program.repeat_delay).await;
+        }
+    }
+    else if program.noreply {
         for i in 0..repeat {
             let params = if program.sequencer {
                 Some(json!(i + 1))
@@ -127,10 +284,25 @@ async fn main() -> AsyncResult<()> {
                 params.clone()
             };
 
-            match client.rpc_request(method.as_str(), params.clone()).await {
+            let result = if program.progress {
+                let (progress, mut reports) = unbounded_channel::<rpc::Progress>();
+
+                tokio::spawn(async move {
+                    while let Some(report) = reports.recv().await {
+                        log::info!("Progress: {}", report);
+                    }
+                });
+
+                client.rpc_request_with_progress(method.as_str(), params.clone(), progress).await
+            }
+            else {
+                client.rpc_request(method.as_str(), params.clone()).await
+            };
+
+            match result {
                 Ok(response) => {
                     if !program.silent {
-                        println!("{}", response.to_string());
+                        println!("{}", response);
                     }
 
                     completed += 1;
@@ -2,6 +2,8 @@ use std::env;
 use std::fs::File;
 use std::io::Write;
 use std::num::ParseIntError;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicUsize,Ordering};
 
 use async_trait::async_trait;
 use clap::Parser;
@@ -12,10 +14,15 @@ use tokio::time::sleep;
 use tokio::time::Duration;
 
 use skein_rpc::AsyncResult;
+use skein_rpc::Compression;
 use skein_rpc::amqp::Worker;
 use skein_rpc::logging;
+use skein_rpc::openrpc;
 use skein_rpc::Responder;
+use skein_rpc::Router;
 use skein_rpc::rpc;
+use skein_rpc::Sink;
+use skein_rpc::Subscriptions;
 
 #[derive(Parser)]
 #[clap(version = env!("CARGO_PKG_VERSION"))]
@@ -33,51 +40,150 @@ struct Program {
     #[clap(long,parse(try_from_str=Self::try_into_duration))]
     timeout_terminate : Option<Duration>,
     #[clap(short,long)]
-    queue : Option<String>
+    queue : Option<String>,
+    #[clap(long)]
+    strict : bool,
+    #[clap(long,possible_values=["gzip","zstd"])]
+    compression : Option<Compression>,
+    #[clap(long)]
+    compression_threshold : Option<usize>,
+    #[clap(long)]
+    concurrency : Option<usize>
 }
 
 impl Program {
     fn try_into_duration(s: &str) -> Result<Duration, ParseIntError> {
-        s.parse().map(|seconds| Duration::from_secs(seconds))
+        s.parse().map(Duration::from_secs)
     }
 }
 
+#[derive(Default)]
+struct WorkerState {
+    handler_count: AtomicUsize,
+    subscriptions: Subscriptions
+}
+
+async fn echo(state: Arc<WorkerState>, request: rpc::Request) -> AsyncResult<Value> {
+    state.handler_count.fetch_add(1, Ordering::SeqCst);
+
+    Ok(request.params().cloned().unwrap_or(json!(null)))
+}
+
+async fn stall(_state: Arc<WorkerState>, _request: rpc::Request, sink: Sink) -> AsyncResult<Value> {
+    for i in 0..10 {
+        sink.progress(rpc::Progress::new().with_percentage(i as f64 * 10.0)).await?;
+
+        sleep(Duration::from_secs(1)).await;
+    }
+
+    Ok(json!(false))
+}
+
+async fn stream(state: Arc<WorkerState>, request: rpc::Request, sink: Sink) -> AsyncResult<Value> {
+    state.handler_count.fetch_add(1, Ordering::SeqCst);
+
+    let items : Vec<Value> = request.params_as()?;
+
+    for item in items {
+        sink.partial(item).await?;
+
+        sleep(Duration::from_secs(1)).await;
+    }
+
+    Ok(json!(null))
+}
+
+async fn subscribe(state: Arc<WorkerState>, _request: rpc::Request, sink: Sink) -> AsyncResult<Value> {
+    let subscriber = sink.subscribe().ok_or("Subscriptions require a reply queue")?;
+    let subscription = subscriber.id().to_string();
+
+    state.subscriptions.insert(subscriber);
+
+    Ok(json!(subscription))
+}
+
+async fn unsubscribe(state: Arc<WorkerState>, request: rpc::Request) -> AsyncResult<Value> {
+    let (subscription,) : (String,) = request.params_as()?;
+
+    Ok(json!(state.subscriptions.remove(&subscription)))
+}
+
+#[derive(Clone)]
 struct WorkerContext {
-    handler_count: usize,
-    receipt_log: Option<File>
+    router: Router<WorkerState>,
+    receipt_log: Option<Arc<Mutex<File>>>
 }
 
 impl WorkerContext {
     fn new(receipt_log: Option<String>) -> Self {
+        let router = Router::default()
+            .with_info("amqp-worker", env!("CARGO_PKG_VERSION"))
+            .with_route("echo", echo)
+            .with_streaming_route("stall", stall)
+            .with_streaming_route("stream", stream)
+            .with_streaming_route("subscribe", subscribe)
+            .with_route("unsubscribe", unsubscribe)
+            .with_route_description(
+                openrpc::Method::new("echo")
+                    .with_summary("Returns the params as given")
+                    .with_param(openrpc::ContentDescriptor::new("params", json!({ })))
+                    .with_result(openrpc::ContentDescriptor::new("params", json!({ })))
+            )
+            .with_route_description(
+                openrpc::Method::new("stall")
+                    .with_summary("Waits ten seconds, reporting progress each second, before returning false")
+                    .with_result(openrpc::ContentDescriptor::new("result", json!({ "type": "boolean" })))
+            )
+            .with_route_description(
+                openrpc::Method::new("stream")
+                    .with_summary("Streams each of the params back as a partial result, one second apart")
+                    .with_param(openrpc::ContentDescriptor::new("params", json!({ "type": "array" })))
+            )
+            .with_route_description(
+                openrpc::Method::new("subscribe")
+                    .with_summary("Subscribes to a count sent every second")
+                    .with_result(openrpc::ContentDescriptor::new("subscription", json!({ "type": "string" })))
+            )
+            .with_route_description(
+                openrpc::Method::new("unsubscribe")
+                    .with_summary("Ends a subscription, returning false if there was no such subscription")
+                    .with_param(openrpc::ContentDescriptor::new("subscription", json!({ "type": "string" })).with_required(true))
+                    .with_result(openrpc::ContentDescriptor::new("result", json!({ "type": "boolean" })))
+            );
+
         Self {
-            handler_count: 0,
-            receipt_log: receipt_log.map(|path| File::create(path.as_str()).unwrap())
+            router,
+            receipt_log: receipt_log.map(|path| Arc::new(Mutex::new(File::create(path.as_str()).unwrap())))
+        }
+    }
+
+    fn state(&self) -> &WorkerState {
+        self.router.state()
+    }
+
+    fn log_receipt(&self, request: &rpc::Request) {
+        if let (Some(log), Some(id)) = (&self.receipt_log, request.id()) {
+            log.lock().unwrap().write_all(format!("{}\n", id).as_bytes()).unwrap();
         }
     }
 }
 
 #[async_trait]
 impl Responder for WorkerContext {
+    fn describe(&self) -> Option<openrpc::Document> {
+        self.router.describe()
+    }
+
     async fn respond(&mut self, request: &rpc::Request) -> AsyncResult<Value> {
-        if let Some(ref mut log) = &mut self.receipt_log {
-            log.write_all(format!("{}\n", request.id()).as_bytes()).unwrap();
-        }
+        self.log_receipt(request);
 
-        match request.method().as_str() {
-            "echo" => {
-                self.handler_count += 1;
+        self.router.respond(request).await
+    }
 
-                Ok(request.params().cloned().unwrap_or(json!(null)))
-            },
-            "stall" => {
-                sleep(Duration::from_secs(10)).await;
+    async fn respond_streaming(&mut self, request: &rpc::Request, sink: &Sink) -> AsyncResult<Value> {
+        self.log_receipt(request);
 
-                Ok(json!(false))
-            },
-            _ => {
-                Err(Box::new(rpc::ErrorResponse::new(-32601, "Method not found", None)))
-            }
-        }
+        self.router.respond_streaming(request, sink).await
     }
 }
 
@@ -101,6 +207,8 @@ async fn main() -> AsyncResult<()> {
 
     let context = WorkerContext::new(program.receipt_log);
 
+    let subscriptions = context.state().subscriptions.clone();
+
     let (worker, terminator) = Worker::new(
         context,
         amqp_url,
@@ -109,6 +217,36 @@ async fn main() -> AsyncResult<()> {
         program.timeout_terminate
     )?;
 
+    let worker = if program.strict {
+        worker.with_strictness(rpc::Strictness::Strict)
+    }
+    else {
+        worker
+    };
+
+    let worker = match program.compression {
+        Some(compression) => worker.with_compression(compression),
+        None => worker
+    };
+
+    let worker = match program.compression_threshold {
+        Some(threshold) => worker.with_compression_threshold(threshold),
+        None => worker
+    };
+
+    let worker = match program.concurrency {
+        Some(concurrency) => worker.with_concurrency(concurrency),
+        None => worker
+    };
+
+    tokio::spawn(async move {
+        for count in 0.. {
+            sleep(Duration::from_secs(1)).await;
+
+            subscriptions.broadcast(json!(count)).await;
+        }
+    });
+
     tokio::spawn(async move {
         tokio::signal::ctrl_c().await.expect("Couldn't bind to CTRL-C handler.");
 
@@ -119,7 +257,7 @@ async fn main() -> AsyncResult<()> {
 
     let worker = worker.run().await??;
 
-    log::info!("Handled {} message(s)", worker.context().handler_count);
+    log::info!("Handled {} message(s)", worker.context().state().handler_count.load(Ordering::SeqCst));
 
     Ok(())
 }
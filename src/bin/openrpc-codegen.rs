@@ -0,0 +1,45 @@
+use std::fs;
+use std::io::Read;
+
+use clap::Parser;
+
+use skein_rpc::AsyncResult;
+use skein_rpc::codegen;
+use skein_rpc::openrpc;
+
+#[derive(Parser)]
+#[clap(version = env!("CARGO_PKG_VERSION"))]
+struct Program {
+    #[clap(short,long,default_value="RpcClient")]
+    name : String,
+    #[clap(short,long)]
+    output : Option<String>,
+    /// Path to the OpenRPC document, or - to read from standard input
+    input : String
+}
+
+fn main() -> AsyncResult<()> {
+    let program = Program::parse();
+
+    let json = if program.input == "-" {
+        let mut json = String::new();
+
+        std::io::stdin().read_to_string(&mut json)?;
+
+        json
+    }
+    else {
+        fs::read_to_string(&program.input)?
+    };
+
+    let document : openrpc::Document = serde_json::from_str(&json)?;
+
+    let source = codegen::generate(&document, &program.name);
+
+    match program.output {
+        Some(path) => fs::write(path, source)?,
+        None => print!("{}", source)
+    }
+
+    Ok(())
+}
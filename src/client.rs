@@ -1,11 +1,139 @@
 use async_trait::async_trait;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
+use uuid::Uuid;
 
 use crate::AsyncResult;
+use crate::rpc;
 
+/// Makes RPC calls over some transport. Only rpc_request is required, with
+/// the rest built on it, though transports that can send batches,
+/// notifications or meta should implement those methods directly, as the
+/// defaults make ordinary requests and wait for each response.
 #[async_trait]
 pub trait Client {
     async fn rpc_request(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<Value>;
-    async fn rpc_request_serialize<T>(&self, method: impl ToString + Send + 'async_trait, params: Option<impl Into<Value> + Send + 'async_trait>) -> AsyncResult<T> where T : From<Value> + Send + 'async_trait;
-    async fn rpc_request_inject(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<String>;
+
+    async fn rpc_request_serialize<T>(&self, method: impl ToString + Send + 'async_trait, params: Option<impl Into<Value> + Send + 'async_trait>) -> AsyncResult<T> where T : From<Value> + Send + 'async_trait {
+        Ok(self.rpc_request(method, params.map(Into::into)).await?.into())
+    }
+
+    /// Calls the method with params serialized from any type, deserializing
+    /// the result into another.
+    async fn rpc_call<P,R>(&self, method: impl ToString + Send + 'async_trait, params: P) -> AsyncResult<R> where P : Serialize + Send + 'async_trait, R : DeserializeOwned {
+        // Params that serialize to null, like (), are omitted entirely.
+        let params = match serde_json::to_value(params)? {
+            Value::Null => None,
+            params => Some(params)
+        };
+
+        let result = self.rpc_request(method, params).await?;
+
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Makes several calls, returning the result or error of each in order.
+    /// By default these are made one after another.
+    async fn rpc_batch(&self, calls: Vec<(String, Option<Value>)>) -> AsyncResult<Vec<Result<Value, rpc::ErrorResponse>>> {
+        let mut results = Vec::with_capacity(calls.len());
+
+        for (method, params) in calls {
+            results.push(match self.rpc_request(method, params).await {
+                Ok(result) => Ok(result),
+                Err(err) => Err(error_response(err)?)
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Sends a request asking for no reply, returning its id. By default
+    /// this goes through rpc_send, and so rpc_request, which sends it as an
+    /// ordinary request and waits for the response only to discard it, so
+    /// transports that can send without waiting should override this.
+    async fn rpc_request_inject(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<String> {
+        let id = Uuid::new_v4().to_string();
+
+        self.rpc_send(rpc::Request::new_noreply(id.clone(), method, params)).await?;
+
+        Ok(id)
+    }
+
+    /// Sends a notification. By default this is sent through rpc_request
+    /// as an ordinary request with an id, waiting for the response only to
+    /// discard it, so transports that can send true notifications should
+    /// override this.
+    async fn rpc_notify(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<()> {
+        self.rpc_send(rpc::Request::new_notification(method, params)).await?;
+
+        Ok(())
+    }
+
+    /// Sends a fully formed request, such as one carrying meta, returning the
+    /// response as-is, or None if no reply was requested. By default this is
+    /// made through rpc_request, so only the method and params are sent, and
+    /// any result is discarded if no reply was requested.
+    async fn rpc_send(&self, request: rpc::Request) -> AsyncResult<Option<rpc::Response>> {
+        let response = match self.rpc_request(request.method(), request.params().cloned()).await {
+            Ok(result) => rpc::Response::result_for(&request, result),
+            Err(err) => rpc::Response::new_error(request.id().cloned().unwrap_or(rpc::Id::Null), error_response(err)?)
+        };
+
+        if request.is_notification() || !request.reply_to() {
+            return Ok(None);
+        }
+
+        Ok(Some(response))
+    }
+}
+
+// Returns the error the other end replied with, or the error itself if it
+// failed some other way.
+fn error_response(err: Box<dyn std::error::Error + Send + Sync>) -> AsyncResult<rpc::ErrorResponse> {
+    Ok(*err.downcast::<rpc::ErrorResponse>()?)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    // Answers requests for "echo" with their params, failing others.
+    struct Echo;
+
+    #[async_trait]
+    impl Client for Echo {
+        async fn rpc_request(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<Value> {
+            match method.to_string().as_str() {
+                "echo" => Ok(params.unwrap_or(Value::Null)),
+                "disconnect" => Err("Disconnected".into()),
+                _ => Err(Box::new(rpc::ErrorResponse::new(rpc::ErrorCode::MethodNotFound, "Method not found", None)))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_defaults() {
+        let result : Vec<u32> = Echo.rpc_call("echo", [ 1, 2 ]).await.unwrap();
+
+        assert_eq!(result, vec![ 1, 2 ]);
+
+        let results = Echo.rpc_batch(vec![ ("echo".to_string(), Some(json!(1))), ("other".to_string(), None) ]).await.unwrap();
+
+        assert_eq!(results[0], Ok(json!(1)));
+        assert!(results[1].as_ref().unwrap_err().is_method_not_found());
+
+        assert!(Echo.rpc_batch(vec![ ("disconnect".to_string(), None) ]).await.is_err());
+
+        let response = Echo.rpc_send(rpc::Request::new(1, "other", None)).await.unwrap().unwrap();
+
+        assert_eq!(response.id(), &rpc::Id::from(1));
+        assert!(response.error().unwrap().is_method_not_found());
+
+        assert_eq!(Echo.rpc_send(rpc::Request::new_notification("other", None)).await.unwrap(), None);
+        assert!(Echo.rpc_notify("disconnect", None).await.is_err());
+        assert!(!Echo.rpc_request_inject("echo", None).await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,270 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::AsyncResult;
+use crate::rpc::Strictness;
+
+pub const JSON_CONTENT_TYPE : &str = "application/json";
+pub const MESSAGEPACK_CONTENT_TYPE : &str = "application/msgpack";
+pub const CBOR_CONTENT_TYPE : &str = "application/cbor";
+
+/// Converts JSON-RPC payloads to and from the bytes carried by a transport.
+/// The content type identifies the encoding so the receiving side can select
+/// the matching codec.
+pub trait Codec : Debug + Send + Sync {
+    fn content_type(&self) -> &'static str;
+
+    fn encode_value(&self, value: &Value) -> AsyncResult<Vec<u8>>;
+
+    fn decode_value(&self, data: &[u8]) -> AsyncResult<Value>;
+
+    /// The built-in format this codec uses, if any, which lets encode and
+    /// decode work on the target type directly. Other codecs convert through
+    /// a Value with encode_value and decode_value.
+    fn format(&self) -> Option<Format> {
+        None
+    }
+}
+
+/// The formats serde can work with directly, without an intermediate Value.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Format {
+    Json,
+    #[cfg(feature="msgpack")]
+    MessagePack,
+    #[cfg(feature="cbor")]
+    Cbor
+}
+
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn content_type(&self) -> &'static str {
+        JSON_CONTENT_TYPE
+    }
+
+    fn encode_value(&self, value: &Value) -> AsyncResult<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode_value(&self, data: &[u8]) -> AsyncResult<Value> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    fn format(&self) -> Option<Format> {
+        Some(Format::Json)
+    }
+}
+
+/// MessagePack, with the `msgpack` feature.
+#[cfg(feature="msgpack")]
+#[derive(Clone,Copy,Debug,Default)]
+pub struct MessagePack;
+
+#[cfg(feature="msgpack")]
+impl Codec for MessagePack {
+    fn content_type(&self) -> &'static str {
+        MESSAGEPACK_CONTENT_TYPE
+    }
+
+    fn encode_value(&self, value: &Value) -> AsyncResult<Vec<u8>> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode_value(&self, data: &[u8]) -> AsyncResult<Value> {
+        Ok(rmp_serde::from_slice(data)?)
+    }
+
+    fn format(&self) -> Option<Format> {
+        Some(Format::MessagePack)
+    }
+}
+
+/// CBOR, with the `cbor` feature.
+#[cfg(feature="cbor")]
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Cbor;
+
+#[cfg(feature="cbor")]
+impl Codec for Cbor {
+    fn content_type(&self) -> &'static str {
+        CBOR_CONTENT_TYPE
+    }
+
+    fn encode_value(&self, value: &Value) -> AsyncResult<Vec<u8>> {
+        let mut data = Vec::new();
+
+        ciborium::into_writer(value, &mut data)?;
+
+        Ok(data)
+    }
+
+    fn decode_value(&self, data: &[u8]) -> AsyncResult<Value> {
+        Ok(ciborium::from_reader(data)?)
+    }
+
+    fn format(&self) -> Option<Format> {
+        Some(Format::Cbor)
+    }
+}
+
+/// Serializes anything into the given codec's encoding.
+pub fn encode<T>(codec: &dyn Codec, item: &T) -> AsyncResult<Vec<u8>> where T : Serialize {
+    match codec.format() {
+        Some(Format::Json) => Ok(serde_json::to_vec(item)?),
+        #[cfg(feature="msgpack")]
+        Some(Format::MessagePack) => Ok(rmp_serde::to_vec_named(item)?),
+        #[cfg(feature="cbor")]
+        Some(Format::Cbor) => {
+            let mut data = Vec::new();
+
+            ciborium::into_writer(item, &mut data)?;
+
+            Ok(data)
+        },
+        None => codec.encode_value(&serde_json::to_value(item)?)
+    }
+}
+
+/// Deserializes anything from the given codec's encoding.
+pub fn decode<T>(codec: &dyn Codec, data: &[u8]) -> AsyncResult<T> where T : DeserializeOwned {
+    match codec.format() {
+        Some(Format::Json) => Ok(serde_json::from_slice(data)?),
+        #[cfg(feature="msgpack")]
+        Some(Format::MessagePack) => Ok(rmp_serde::from_slice(data)?),
+        #[cfg(feature="cbor")]
+        Some(Format::Cbor) => Ok(ciborium::from_reader(data)?),
+        None => Ok(serde_json::from_value(codec.decode_value(data)?)?)
+    }
+}
+
+/// Returns the built-in codec for a content type, going by the media type
+/// alone so parameters such as charset and differences in case are ignored.
+/// A missing content type is presumed to be JSON, as are any that aren't
+/// recognised, such as those from older publishers, unless strict. Those
+/// whose feature isn't enabled are unrecognised.
+pub fn for_content_type(content_type: Option<&str>, strictness: Strictness) -> Option<Arc<dyn Codec>> {
+    let media_type = content_type.map(media_type).unwrap_or_default();
+
+    match media_type.as_str() {
+        "" | JSON_CONTENT_TYPE => Some(Arc::new(Json)),
+        #[cfg(feature="msgpack")]
+        MESSAGEPACK_CONTENT_TYPE | "application/x-msgpack" => Some(Arc::new(MessagePack)),
+        #[cfg(feature="cbor")]
+        CBOR_CONTENT_TYPE => Some(Arc::new(Cbor)),
+        _ if strictness == Strictness::Strict => None,
+        _ => {
+            log::warn!("Error: Unrecognised content type {:?}, presuming JSON", media_type);
+
+            Some(Arc::new(Json))
+        }
+    }
+}
+
+// The content type without any parameters, in lower case.
+fn media_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::rpc::Request;
+
+    use super::*;
+
+    fn example() -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "echo",
+            "params": [ "text", 1.5, -3, true, null, { "nested": [ 1, 2 ] } ]
+        })
+    }
+
+    fn assert_round_trip(codec: &dyn Codec) {
+        let data = codec.encode_value(&example()).unwrap();
+
+        assert_eq!(codec.decode_value(&data).unwrap(), example());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        assert_round_trip(&Json);
+    }
+
+    #[cfg(feature="msgpack")]
+    #[test]
+    fn test_messagepack_round_trip() {
+        assert_round_trip(&MessagePack);
+    }
+
+    #[cfg(feature="cbor")]
+    #[test]
+    fn test_cbor_round_trip() {
+        assert_round_trip(&Cbor);
+    }
+
+    // Has no built-in format, so is used through Values.
+    #[derive(Debug)]
+    struct Custom;
+
+    impl Codec for Custom {
+        fn content_type(&self) -> &'static str {
+            "application/x-custom"
+        }
+
+        fn encode_value(&self, value: &Value) -> AsyncResult<Vec<u8>> {
+            Json.encode_value(value)
+        }
+
+        fn decode_value(&self, data: &[u8]) -> AsyncResult<Value> {
+            Json.decode_value(data)
+        }
+    }
+
+    #[test]
+    fn test_typed() {
+        let codecs : Vec<&dyn Codec> = vec![
+            &Json,
+            &Custom,
+            #[cfg(feature="msgpack")]
+            &MessagePack,
+            #[cfg(feature="cbor")]
+            &Cbor
+        ];
+
+        let request = Request::new(7, "echo", Some(json!([ "text", 1.5, -3, true, null ])));
+
+        for codec in codecs {
+            let data = encode(codec, &request).unwrap();
+
+            assert_eq!(decode::<Request>(codec, &data).unwrap(), request);
+            assert_eq!(codec.decode_value(&data).unwrap(), serde_json::to_value(&request).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_for_content_type() {
+        for strictness in [ Strictness::Lenient, Strictness::Strict ] {
+            assert_eq!(for_content_type(None, strictness).unwrap().content_type(), JSON_CONTENT_TYPE);
+            assert_eq!(for_content_type(Some("application/json; charset=utf-8"), strictness).unwrap().content_type(), JSON_CONTENT_TYPE);
+            assert_eq!(for_content_type(Some(" Application/JSON "), strictness).unwrap().content_type(), JSON_CONTENT_TYPE);
+
+            #[cfg(feature="msgpack")]
+            assert_eq!(for_content_type(Some("application/msgpack"), strictness).unwrap().content_type(), MESSAGEPACK_CONTENT_TYPE);
+            #[cfg(feature="cbor")]
+            assert_eq!(for_content_type(Some("application/cbor;q=1"), strictness).unwrap().content_type(), CBOR_CONTENT_TYPE);
+        }
+
+        // Unrecognised types are presumed to be JSON unless strict.
+        assert_eq!(for_content_type(Some("text/plain"), Strictness::Lenient).unwrap().content_type(), JSON_CONTENT_TYPE);
+        assert!(for_content_type(Some("text/plain"), Strictness::Strict).is_none());
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use serde_json::Value;
+
+use crate::openrpc::{ContentDescriptor,Document,Method,ParamStructure};
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const",
+    "continue", "do", "dyn", "else", "enum", "extern", "false", "final", "fn",
+    "for", "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move",
+    "mut", "override", "priv", "pub", "ref", "return", "static", "struct",
+    "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield"
+];
+
+// These can't be used as raw identifiers, and the rest would collide with
+// the generated client's own methods.
+const RESERVED: &[&str] = &[ "crate", "self", "Self", "super", "client", "into_inner", "new" ];
+
+/// Generates Rust source for a typed client named `name` which wraps any
+/// skein_rpc::Client, with one async method per method in the document.
+/// Params are sent by name, so each method with params gets a corresponding
+/// serde struct, as do any object schemas with declared properties, unless
+/// the method's paramStructure is by-position, where they're sent in order.
+/// Names which come out the same once made into Rust identifiers are told
+/// apart with a numbered suffix.
+///
+/// The output uses fully qualified paths throughout so it can be written out
+/// from a build.rs and pulled in with include!, with the including crate
+/// depending on serde (with derive) and serde_json.
+pub fn generate(document: &Document, name: &str) -> String {
+    let mut generator = Generator::default();
+
+    let mut methods = String::new();
+
+    for method in &document.methods {
+        generator.method(&mut methods, method);
+    }
+
+    let mut out = String::new();
+
+    writeln!(out, "// Generated from the OpenRPC description of {} {}.", document.info.title, document.info.version).unwrap();
+    writeln!(out, "// Changes made here will be overwritten.").unwrap();
+
+    for definition in &generator.definitions {
+        out.push('\n');
+        out.push_str(definition);
+    }
+
+    out.push('\n');
+
+    if let Some(description) = &document.info.description {
+        write_doc(&mut out, "", description);
+    }
+
+    writeln!(out, "pub struct {}<C> where C : ::skein_rpc::Client + Sync {{", name).unwrap();
+    writeln!(out, "    client: C").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "impl<C> {}<C> where C : ::skein_rpc::Client + Sync {{", name).unwrap();
+    writeln!(out, "    pub fn new(client: C) -> Self {{").unwrap();
+    writeln!(out, "        Self {{ client }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    pub fn client(&self) -> &C {{").unwrap();
+    writeln!(out, "        &self.client").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    pub fn into_inner(self) -> C {{").unwrap();
+    writeln!(out, "        self.client").unwrap();
+    writeln!(out, "    }}").unwrap();
+    out.push_str(&methods);
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+#[derive(Default)]
+struct Generator {
+    definitions: Vec<String>,
+    names: HashSet<String>,
+    methods: HashSet<String>
+}
+
+impl Generator {
+    fn method(&mut self, out: &mut String, method: &Method) {
+        let pascal = to_pascal_case(&method.name);
+
+        let result = match &method.result {
+            Some(result) => self.type_for(&result.schema, &format!("{}Result", pascal)),
+            None => "::serde_json::Value".to_string()
+        };
+
+        let mut args = vec![ "&self".to_string() ];
+
+        let params = if method.params.is_empty() {
+            "()".to_string()
+        }
+        else if method.param_structure == ParamStructure::ByPosition {
+            // A tuple serializes as an array of its members in order.
+            let fields = self.fields_for(&format!("{}Params", pascal), &method.params);
+
+            args.extend(fields.iter().map(|(ident, ty)| format!("{}: {}", ident, ty)));
+
+            let idents : Vec<&str> = fields.iter().map(|(ident, _)| ident.as_str()).collect();
+
+            match idents.as_slice() {
+                [ ident ] => format!("({},)", ident),
+                idents => format!("({})", idents.join(", "))
+            }
+        }
+        else {
+            let (params_name, fields) = self.struct_for(&format!("{}Params", pascal), &method.params);
+
+            args.extend(fields.iter().map(|(ident, ty)| format!("{}: {}", ident, ty)));
+
+            let idents : Vec<&str> = fields.iter().map(|(ident, _)| ident.as_str()).collect();
+
+            format!("{} {{ {} }}", params_name, idents.join(", "))
+        };
+
+        let ident = unique_ident(&mut self.methods, to_identifier(&method.name));
+
+        out.push('\n');
+
+        if let Some(summary) = &method.summary {
+            write_doc(out, "    ", summary);
+        }
+
+        writeln!(out, "    pub async fn {}({}) -> ::skein_rpc::AsyncResult<{}> {{", ident, args.join(", "), result).unwrap();
+        writeln!(out, "        ::skein_rpc::Client::rpc_call(&self.client, {:?}, {}).await", method.name, params).unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+
+    fn param_type(&mut self, parent: &str, param: &ContentDescriptor) -> String {
+        let ty = self.type_for(&param.schema, &format!("{}{}", parent, to_pascal_case(&param.name)));
+
+        if param.required {
+            ty
+        }
+        else {
+            format!("Option<{}>", ty)
+        }
+    }
+
+    fn type_for(&mut self, schema: &Value, name: &str) -> String {
+        match schema.get("type").and_then(|t| t.as_str()) {
+            Some("string") => "String".to_string(),
+            Some("integer") => "i64".to_string(),
+            Some("number") => "f64".to_string(),
+            Some("boolean") => "bool".to_string(),
+            Some("null") => "()".to_string(),
+            Some("array") => {
+                match schema.get("items") {
+                    Some(items) => format!("Vec<{}>", self.type_for(items, &format!("{}Item", name))),
+                    None => "Vec<::serde_json::Value>".to_string()
+                }
+            },
+            Some("object") => {
+                match schema.get("properties").and_then(|p| p.as_object()) {
+                    Some(properties) if !properties.is_empty() => {
+                        let required : Vec<&str> = schema.get("required")
+                            .and_then(|r| r.as_array())
+                            .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+                            .unwrap_or_default();
+
+                        let fields : Vec<ContentDescriptor> = properties.iter().map(|(key, schema)| {
+                            ContentDescriptor::new(key, schema.clone()).with_required(required.contains(&key.as_str()))
+                        }).collect();
+
+                        self.struct_for(name, &fields).0
+                    },
+                    _ => "::serde_json::Map<String, ::serde_json::Value>".to_string()
+                }
+            },
+            _ => "::serde_json::Value".to_string()
+        }
+    }
+
+    // Returns the identifier and type of each field, where the identifiers are
+    // unique among them.
+    fn fields_for(&mut self, parent: &str, fields: &[ContentDescriptor]) -> Vec<(String,String)> {
+        let mut idents = HashSet::new();
+
+        fields.iter().map(|field| {
+            let ty = self.param_type(parent, field);
+
+            (unique_ident(&mut idents, to_identifier(&field.name)), ty)
+        }).collect()
+    }
+
+    // Returns the name of the struct along with the identifier and type of
+    // each of its fields.
+    fn struct_for(&mut self, name: &str, fields: &[ContentDescriptor]) -> (String, Vec<(String,String)>) {
+        let name = self.unique_name(name);
+
+        let mut definition = String::new();
+
+        writeln!(definition, "#[derive(Clone,Debug,::serde::Deserialize,::serde::Serialize)]").unwrap();
+        writeln!(definition, "pub struct {} {{", name).unwrap();
+
+        let members = self.fields_for(&name, fields);
+        let count = members.len();
+
+        for (i, (field, (ident, ty))) in fields.iter().zip(&members).enumerate() {
+            if let Some(summary) = &field.summary {
+                write_doc(&mut definition, "    ", summary);
+            }
+
+            if ident.trim_start_matches("r#") != field.name {
+                writeln!(definition, "    #[serde(rename = {:?})]", field.name).unwrap();
+            }
+
+            if !field.required {
+                writeln!(definition, "    #[serde(default, skip_serializing_if = \"Option::is_none\")]").unwrap();
+            }
+
+            writeln!(definition, "    pub {}: {}{}", ident, ty, if i + 1 < count { "," } else { "" }).unwrap();
+        }
+
+        writeln!(definition, "}}").unwrap();
+
+        self.definitions.push(definition);
+
+        (name, members)
+    }
+
+    fn unique_name(&mut self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut n = 1;
+
+        while self.names.contains(&candidate) {
+            n += 1;
+            candidate = format!("{}{}", name, n);
+        }
+
+        self.names.insert(candidate.clone());
+
+        candidate
+    }
+}
+
+// Returns the identifier, or if it's already taken, the first free one with
+// a numbered suffix.
+fn unique_ident(taken: &mut HashSet<String>, ident: String) -> String {
+    let mut candidate = ident.clone();
+    let mut n = 1;
+
+    while taken.contains(&candidate) {
+        n += 1;
+        candidate = format!("{}_{}", ident.trim_start_matches("r#"), n);
+    }
+
+    taken.insert(candidate.clone());
+
+    candidate
+}
+
+fn write_doc(out: &mut String, indent: &str, text: &str) {
+    for line in text.lines() {
+        writeln!(out, "{}/// {}", indent, line).unwrap();
+    }
+}
+
+fn words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+
+    for c in s.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+
+            prev_lower = false;
+
+            continue;
+        }
+
+        if c.is_ascii_uppercase() && prev_lower {
+            words.push(std::mem::take(&mut word));
+        }
+
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        word.push(c);
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+fn to_pascal_case(s: &str) -> String {
+    words(s).iter().map(|w| {
+        let mut chars = w.chars();
+
+        match chars.next() {
+            Some(first) => first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase(),
+            None => String::new()
+        }
+    }).collect()
+}
+
+fn to_identifier(s: &str) -> String {
+    let ident = words(s).iter().map(|w| w.to_ascii_lowercase()).collect::<Vec<_>>().join("_");
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", ident)
+    }
+    else if RESERVED.contains(&ident.as_str()) {
+        format!("{}_", ident)
+    }
+    else if KEYWORDS.contains(&ident.as_str()) {
+        format!("r#{}", ident)
+    }
+    else {
+        ident
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(to_identifier("rpc.discover"), "rpc_discover");
+        assert_eq!(to_identifier("getUserName"), "get_user_name");
+        assert_eq!(to_identifier("type"), "r#type");
+        assert_eq!(to_identifier("self"), "self_");
+        assert_eq!(to_identifier("2fa"), "_2fa");
+        assert_eq!(to_pascal_case("user.get_by-id"), "UserGetById");
+        assert_eq!(to_pascal_case("getUser"), "GetUser");
+    }
+
+    #[test]
+    fn test_generate() {
+        let document : Document = serde_json::from_value(json!({
+            "openrpc": "1.2.6",
+            "info": { "title": "example", "version": "1.0.0" },
+            "methods": [
+                {
+                    "name": "user.find",
+                    "summary": "Finds a user",
+                    "params": [
+                        { "name": "userId", "required": true, "schema": { "type": "integer" } },
+                        { "name": "type", "schema": { "type": "string" } }
+                    ],
+                    "result": {
+                        "name": "user",
+                        "schema": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string" },
+                                "tags": { "type": "array", "items": { "type": "string" } }
+                            },
+                            "required": [ "name" ]
+                        }
+                    }
+                },
+                { "name": "ping", "params": [ ] }
+            ]
+        })).unwrap();
+
+        let source = generate(&document, "ExampleClient");
+
+        assert!(source.contains("pub struct UserFindParams {"));
+        assert!(source.contains("    #[serde(rename = \"userId\")]\n    pub user_id: i64,"));
+        assert!(source.contains("    pub r#type: Option<String>\n"));
+        assert!(source.contains("pub struct UserFindResult {"));
+        assert!(source.contains("    pub tags: Option<Vec<String>>\n"));
+        assert!(source.contains("pub struct ExampleClient<C> where C : ::skein_rpc::Client + Sync {"));
+        assert!(source.contains("    /// Finds a user\n    pub async fn user_find(&self, user_id: i64, r#type: Option<String>) -> ::skein_rpc::AsyncResult<UserFindResult> {"));
+        assert!(source.contains("::skein_rpc::Client::rpc_call(&self.client, \"user.find\", UserFindParams { user_id, r#type }).await"));
+        assert!(source.contains("pub async fn ping(&self) -> ::skein_rpc::AsyncResult<::serde_json::Value> {"));
+        assert!(source.contains("::skein_rpc::Client::rpc_call(&self.client, \"ping\", ()).await"));
+    }
+
+    #[test]
+    fn test_distinct_identifiers() {
+        let document : Document = serde_json::from_value(json!({
+            "openrpc": "1.2.6",
+            "info": { "title": "example", "version": "1.0.0" },
+            "methods": [
+                {
+                    "name": "user.find",
+                    "params": [
+                        { "name": "userId", "required": true, "schema": { "type": "integer" } },
+                        { "name": "user_id", "required": true, "schema": { "type": "integer" } }
+                    ]
+                },
+                { "name": "user_find", "paramStructure": "by-position", "params": [ { "name": "name", "required": true, "schema": { "type": "string" } } ] }
+            ]
+        })).unwrap();
+
+        let source = generate(&document, "ExampleClient");
+
+        assert!(source.contains("    #[serde(rename = \"user_id\")]\n    pub user_id_2: i64\n"));
+        assert!(source.contains("pub async fn user_find(&self, user_id: i64, user_id_2: i64)"));
+        assert!(source.contains("pub async fn user_find_2(&self, name: String)"));
+        assert!(source.contains("::skein_rpc::Client::rpc_call(&self.client, \"user_find\", (name,)).await"));
+    }
+
+    // The fixture is compiled and exercised by tests/codegen.rs.
+    #[test]
+    fn test_fixture() {
+        let document : Document = serde_json::from_str(include_str!("../tests/fixtures/example.openrpc.json")).unwrap();
+
+        assert_eq!(generate(&document, "ExampleClient"), include_str!("../tests/fixtures/example_client.rs"));
+    }
+}
@@ -0,0 +1,263 @@
+use std::borrow::Cow;
+use std::fmt;
+#[cfg(any(feature="gzip",feature="zstd"))]
+use std::io::Read;
+#[cfg(feature="gzip")]
+use std::io::Write;
+use std::str::FromStr;
+
+#[cfg(feature="gzip")]
+use flate2::Compression as GzipLevel;
+#[cfg(feature="gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature="gzip")]
+use flate2::write::GzEncoder;
+
+use crate::AsyncResult;
+
+pub const GZIP_ENCODING : &str = "gzip";
+pub const ZSTD_ENCODING : &str = "zstd";
+pub const IDENTITY_ENCODING : &str = "identity";
+
+/// Payloads smaller than this are sent as-is unless configured otherwise.
+pub const DEFAULT_THRESHOLD : usize = 64 * 1024;
+
+/// Payloads which decompress to more than this are rejected unless configured
+/// otherwise, so a small message can't exhaust memory.
+pub const DEFAULT_MAX_DECOMPRESSED : usize = 64 * 1024 * 1024;
+
+/// A compression scheme identified by its content encoding, each of which
+/// is available with the feature of the same name.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Compression {
+    #[cfg(feature="gzip")]
+    Gzip,
+    #[cfg(feature="zstd")]
+    Zstd
+}
+
+// With neither feature enabled there are no schemes, and nothing to use the
+// arguments below.
+#[cfg_attr(not(any(feature="gzip",feature="zstd")), allow(unused_variables))]
+impl Compression {
+    pub fn content_encoding(&self) -> &'static str {
+        match *self {
+            #[cfg(feature="gzip")]
+            Self::Gzip => GZIP_ENCODING,
+            #[cfg(feature="zstd")]
+            Self::Zstd => ZSTD_ENCODING
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> AsyncResult<Vec<u8>> {
+        match *self {
+            #[cfg(feature="gzip")]
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), GzipLevel::default());
+
+                encoder.write_all(data)?;
+
+                Ok(encoder.finish()?)
+            },
+            #[cfg(feature="zstd")]
+            Self::Zstd => Ok(zstd::encode_all(data, 0)?)
+        }
+    }
+
+    /// Decompresses the data, failing if it comes to more than limit bytes.
+    pub fn decompress(&self, data: &[u8], limit: usize) -> AsyncResult<Vec<u8>> {
+        match *self {
+            #[cfg(feature="gzip")]
+            Self::Gzip => read_limited(GzDecoder::new(data), limit),
+            #[cfg(feature="zstd")]
+            Self::Zstd => read_limited(zstd::Decoder::new(data)?, limit)
+        }
+    }
+
+    /// Returns the compression matching a content encoding, where None
+    /// indicates no compression was applied.
+    pub fn for_content_encoding(content_encoding: Option<&str>) -> Result<Option<Self>, UnsupportedEncoding> {
+        match content_encoding {
+            None | Some("") | Some(IDENTITY_ENCODING) => Ok(None),
+            Some(encoding) => encoding.parse().map(Some)
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.content_encoding())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = UnsupportedEncoding;
+
+    fn from_str(s: &str) -> Result<Self, UnsupportedEncoding> {
+        match s {
+            #[cfg(feature="gzip")]
+            GZIP_ENCODING => Ok(Self::Gzip),
+            #[cfg(feature="zstd")]
+            ZSTD_ENCODING => Ok(Self::Zstd),
+            _ => Err(UnsupportedEncoding(s.to_string()))
+        }
+    }
+}
+
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct UnsupportedEncoding(pub String);
+
+impl fmt::Display for UnsupportedEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unsupported content encoding {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedEncoding { }
+
+/// The data decompressed to more than the limit given.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub struct TooLarge(pub usize);
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Decompressed content exceeds {} bytes", self.0)
+    }
+}
+
+impl std::error::Error for TooLarge { }
+
+// Reads everything up to the limit, reading one byte past it to tell whether
+// there was more.
+#[cfg(any(feature="gzip",feature="zstd"))]
+fn read_limited(reader: impl Read, limit: usize) -> AsyncResult<Vec<u8>> {
+    let mut decoded = Vec::new();
+
+    reader.take((limit as u64).saturating_add(1)).read_to_end(&mut decoded)?;
+
+    if decoded.len() > limit {
+        return Err(Box::new(TooLarge(limit)));
+    }
+
+    Ok(decoded)
+}
+
+/// Compression settings. Outgoing compression is applied only to payloads at
+/// or above the threshold since small payloads gain little, and incoming
+/// payloads may decompress to no more than max_decompressed bytes.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub struct Compressor {
+    pub compression: Option<Compression>,
+    pub threshold: usize,
+    pub max_decompressed: usize
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self {
+            compression: None,
+            threshold: DEFAULT_THRESHOLD,
+            max_decompressed: DEFAULT_MAX_DECOMPRESSED
+        }
+    }
+}
+
+impl Compressor {
+    pub fn new(compression: Option<Compression>, threshold: usize) -> Self {
+        Self {
+            compression,
+            threshold,
+            max_decompressed: DEFAULT_MAX_DECOMPRESSED
+        }
+    }
+
+    /// Compresses the payload if warranted, returning it along with the
+    /// content encoding to be declared, if any.
+    pub fn apply(&self, data: Vec<u8>) -> AsyncResult<(Vec<u8>, Option<&'static str>)> {
+        match self.compression {
+            Some(compression) if data.len() >= self.threshold => {
+                Ok((compression.compress(&data)?, Some(compression.content_encoding())))
+            },
+            _ => Ok((data, None))
+        }
+    }
+}
+
+/// Reverses any compression declared by the content encoding, failing if the
+/// data comes to more than limit bytes once decompressed.
+pub fn decompress<'a>(content_encoding: Option<&str>, data: &'a [u8], limit: usize) -> AsyncResult<Cow<'a, [u8]>> {
+    match Compression::for_content_encoding(content_encoding)? {
+        Some(compression) => Ok(Cow::Owned(compression.decompress(data, limit)?)),
+        None => Ok(Cow::Borrowed(data))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Whichever schemes are enabled.
+    const COMPRESSIONS : &[Compression] = &[
+        #[cfg(feature="gzip")]
+        Compression::Gzip,
+        #[cfg(feature="zstd")]
+        Compression::Zstd
+    ];
+
+    fn example() -> Vec<u8> {
+        "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"example\"}".repeat(100).into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &compression in COMPRESSIONS {
+            let data = compression.compress(&example()).unwrap();
+
+            assert!(data.len() < example().len());
+            assert_eq!(decompress(Some(compression.content_encoding()), &data, DEFAULT_MAX_DECOMPRESSED).unwrap().as_ref(), example().as_slice());
+        }
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        for &compression in COMPRESSIONS {
+            let data = compression.compress(&example()).unwrap();
+
+            assert_eq!(compression.decompress(&data, example().len()).unwrap(), example());
+            assert_eq!(compression.decompress(&data, usize::MAX).unwrap(), example());
+
+            let err = compression.decompress(&data, example().len() - 1).unwrap_err();
+
+            assert_eq!(err.downcast_ref::<TooLarge>(), Some(&TooLarge(example().len() - 1)));
+        }
+
+        assert!(decompress(None, &example(), 1).is_ok());
+    }
+
+    #[cfg(feature="zstd")]
+    #[test]
+    fn test_compressor_threshold() {
+        let compressor = Compressor::new(Some(Compression::Zstd), 1024);
+
+        assert_eq!(compressor.apply(b"small".to_vec()).unwrap(), (b"small".to_vec(), None));
+        assert_eq!(compressor.apply(example()).unwrap().1, Some(ZSTD_ENCODING));
+
+        assert_eq!(Compressor::default().apply(example()).unwrap(), (example(), None));
+    }
+
+    #[test]
+    fn test_content_encoding() {
+        assert_eq!(Compression::for_content_encoding(None), Ok(None));
+        assert_eq!(Compression::for_content_encoding(Some("identity")), Ok(None));
+        assert!(Compression::for_content_encoding(Some("br")).is_err());
+
+        #[cfg(feature="gzip")]
+        {
+            assert_eq!(Compression::for_content_encoding(Some("gzip")), Ok(Some(Compression::Gzip)));
+            assert!(decompress(Some("gzip"), b"not gzip", DEFAULT_MAX_DECOMPRESSED).is_err());
+        }
+
+        #[cfg(not(feature="gzip"))]
+        assert!(Compression::for_content_encoding(Some("gzip")).is_err());
+    }
+}
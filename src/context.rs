@@ -0,0 +1,77 @@
+use std::time::{Duration,SystemTime};
+
+use crate::rpc::Meta;
+
+/// How a request was received, as filled in by the transport that delivered
+/// it. This is available to handlers through rpc::Request::context, and
+/// anything a transport doesn't support is left unset, so handlers written
+/// against one transport work unchanged with another.
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct RequestContext {
+    /// The transport's identifier for the delivery, such as the AMQP delivery
+    /// tag.
+    pub delivery_tag: Option<u64>,
+    /// True if the request has been delivered before without being
+    /// acknowledged, so it may already have been partly handled.
+    pub redelivered: bool,
+    /// Where the reply will be sent.
+    pub reply_to: Option<String>,
+    /// Every header the request arrived with, including those used by the
+    /// transport itself, converted to strings.
+    pub headers: Meta,
+    /// When the sender says the request was sent.
+    pub timestamp: Option<SystemTime>,
+    /// The queue the request was taken from.
+    pub queue: Option<String>,
+    /// When the request was received.
+    pub received: Option<SystemTime>,
+    /// When handling the request will be abandoned, either as the caller has
+    /// stopped waiting or as the handler has run out of time.
+    pub deadline: Option<SystemTime>
+}
+
+impl RequestContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how long remains before the deadline, which is zero if it has
+    /// already passed, or None if there is no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+    }
+
+    /// Returns how long ago the request was received, if known.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.received.map(|received| received.elapsed().unwrap_or(Duration::ZERO))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remaining() {
+        let context = RequestContext::new();
+
+        assert_eq!(context.remaining(), None);
+        assert_eq!(context.elapsed(), None);
+
+        let context = RequestContext {
+            received: Some(SystemTime::now()),
+            deadline: Some(SystemTime::now() + Duration::from_secs(60)),
+            ..RequestContext::default()
+        };
+
+        assert!(context.remaining().unwrap() > Duration::from_secs(50));
+        assert!(context.elapsed().unwrap() < Duration::from_secs(10));
+
+        let context = RequestContext {
+            deadline: Some(SystemTime::now() - Duration::from_secs(1)),
+            ..RequestContext::default()
+        };
+
+        assert_eq!(context.remaining(), Some(Duration::ZERO));
+    }
+}
@@ -2,16 +2,64 @@ extern crate async_trait;
 extern crate clap;
 extern crate tokio;
 
+#[cfg(feature = "amqp")]
 pub mod amqp;
 
 mod client;
 pub use client::Client;
 
+pub mod codec;
+pub use codec::Codec;
+
+pub mod codegen;
+
+pub mod compression;
+pub use compression::Compression;
+
+pub mod context;
+pub use context::RequestContext;
+
 pub mod logging;
 
+pub mod middleware;
+pub use middleware::Middleware;
+
+pub mod openrpc;
+
+pub mod params;
+pub use params::{Param,Params};
+
 pub mod rpc;
 
 mod responder;
 pub use responder::Responder;
 
+pub mod router;
+pub use router::Router;
+
+pub mod sink;
+pub use sink::{Notifier,Sink};
+
+pub mod subscription;
+pub use subscription::{Subscriber,Subscriptions};
+
+#[cfg(feature = "tower")]
+pub mod tower;
+
+// Implementations of service traits need async_trait, re-exported here so
+// it's at hand alongside the macro.
+#[cfg(feature = "macros")]
+pub use async_trait::async_trait;
+#[cfg(feature = "macros")]
+pub use skein_rpc_macros::service;
+
+// Used by code generated with the service macro so that crates using it
+// needn't depend on these directly.
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use serde;
+    pub use serde_json;
+}
+
 pub type AsyncResult<T,E=Box<dyn std::error::Error + Sync + Send>> = std::result::Result<T, E>;
@@ -0,0 +1,382 @@
+use std::any::Any;
+use std::collections::{BTreeMap,HashMap};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc,Mutex};
+use std::time::{Duration,Instant};
+
+use async_trait::async_trait;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+use crate::AsyncResult;
+use crate::openrpc;
+use crate::params::Params;
+use crate::Responder;
+use crate::rpc;
+use crate::sink::Sink;
+
+// The responder a chain of middleware ends at, with its type erased so that
+// Next needn't be generic.
+trait Endpoint : Send {
+    fn call<'a>(&'a mut self, request: &'a rpc::Request, sink: &'a Sink) -> BoxFuture<'a, AsyncResult<Value>>;
+}
+
+impl<R> Endpoint for R where R : Responder {
+    fn call<'a>(&'a mut self, request: &'a rpc::Request, sink: &'a Sink) -> BoxFuture<'a, AsyncResult<Value>> {
+        self.respond_streaming(request, sink)
+    }
+}
+
+/// The rest of the chain after a middleware, ending at the responder.
+pub struct Next<'a> {
+    endpoint: &'a mut dyn Endpoint
+}
+
+impl<'a> Next<'a> {
+    /// Passes the request on, which may differ from the one received.
+    pub async fn run(self, request: &rpc::Request, sink: &Sink) -> AsyncResult<Value> {
+        self.endpoint.call(request, sink).await
+    }
+}
+
+/// Wraps the handling of each request, being able to inspect or alter the
+/// request before passing it on, answer it directly instead, or act on the
+/// result.
+#[async_trait]
+pub trait Middleware : Send + Sync + 'static {
+    async fn handle(&self, request: &rpc::Request, sink: &Sink, next: Next<'_>) -> AsyncResult<Value>;
+}
+
+/// A Responder with middleware around it, as made by
+/// Responder::with_middleware. When layered repeatedly, the last added runs
+/// first. It's Clone when both are, so a Worker can handle deliveries
+/// concurrently with clones of it.
+pub struct Layered<R,M> {
+    responder: R,
+    middleware: M
+}
+
+impl<R,M> Layered<R,M> where R : Responder, M : Middleware {
+    pub fn new(responder: R, middleware: M) -> Self {
+        Self { responder, middleware }
+    }
+
+    pub fn responder(&self) -> &R {
+        &self.responder
+    }
+
+    pub fn middleware(&self) -> &M {
+        &self.middleware
+    }
+
+    pub fn into_inner(self) -> R {
+        self.responder
+    }
+}
+
+impl<R,M> Clone for Layered<R,M> where R : Clone, M : Clone {
+    fn clone(&self) -> Self {
+        Self {
+            responder: self.responder.clone(),
+            middleware: self.middleware.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl<R,M> Responder for Layered<R,M> where R : Responder, M : Middleware {
+    fn prepare_request(&self, request: rpc::Request) -> rpc::Request {
+        self.responder.prepare_request(request)
+    }
+
+    fn describe(&self) -> Option<openrpc::Document> {
+        self.responder.describe()
+    }
+
+    async fn respond(&mut self, request: &rpc::Request) -> AsyncResult<Value> {
+        self.respond_streaming(request, &Sink::discard()).await
+    }
+
+    async fn respond_streaming(&mut self, request: &rpc::Request, sink: &Sink) -> AsyncResult<Value> {
+        let next = Next { endpoint: &mut self.responder };
+
+        self.middleware.handle(request, sink, next).await
+    }
+}
+
+/// Logs each request handled, with how long it took and whether it failed.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Logging;
+
+#[async_trait]
+impl Middleware for Logging {
+    async fn handle(&self, request: &rpc::Request, sink: &Sink, next: Next<'_>) -> AsyncResult<Value> {
+        let start = Instant::now();
+        let result = next.run(request, sink).await;
+
+        match &result {
+            Ok(_) => {
+                log::info!("Handled {} in {:?}", request, start.elapsed());
+            },
+            Err(err) => {
+                log::warn!("Error: Request {} failed after {:?} {}", request, start.elapsed(), err);
+            }
+        }
+
+        result
+    }
+}
+
+/// Rejects requests the given check doesn't allow with the error it returns,
+/// such as those without a valid token among their meta.
+#[derive(Clone)]
+pub struct Authorize<F> {
+    check: F
+}
+
+impl<F> Authorize<F> where F : Fn(&rpc::Request) -> Result<(), rpc::ErrorResponse> + Send + Sync + 'static {
+    pub fn new(check: F) -> Self {
+        Self { check }
+    }
+}
+
+#[async_trait]
+impl<F> Middleware for Authorize<F> where F : Fn(&rpc::Request) -> Result<(), rpc::ErrorResponse> + Send + Sync + 'static {
+    async fn handle(&self, request: &rpc::Request, sink: &Sink, next: Next<'_>) -> AsyncResult<Value> {
+        (self.check)(request)?;
+
+        next.run(request, sink).await
+    }
+}
+
+/// Counts for a single method as collected by Metrics.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub struct MethodMetrics {
+    pub calls: u64,
+    pub errors: u64,
+    pub elapsed: Duration
+}
+
+/// Collects calls, errors and time spent per method. Clones share the same
+/// counts, so one can be kept to read them while another is layered.
+#[derive(Clone,Debug,Default)]
+pub struct Metrics {
+    methods: Arc<Mutex<BTreeMap<String,MethodMetrics>>>
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, method: &str) -> Option<MethodMetrics> {
+        self.methods.lock().unwrap().get(method).copied()
+    }
+
+    /// Returns the counts for every method called so far.
+    pub fn snapshot(&self) -> BTreeMap<String,MethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Middleware for Metrics {
+    async fn handle(&self, request: &rpc::Request, sink: &Sink, next: Next<'_>) -> AsyncResult<Value> {
+        let start = Instant::now();
+        let result = next.run(request, sink).await;
+
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry(request.method().clone()).or_default();
+
+        metrics.calls += 1;
+        metrics.elapsed += start.elapsed();
+
+        if result.is_err() {
+            metrics.errors += 1;
+        }
+
+        result
+    }
+}
+
+/// Turns a panic while handling a request into an internal error reply,
+/// rather than taking down the worker. The responder is kept in use
+/// afterwards, so any state it was part way through changing is left as-is.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct CatchPanic;
+
+#[async_trait]
+impl Middleware for CatchPanic {
+    async fn handle(&self, request: &rpc::Request, sink: &Sink, next: Next<'_>) -> AsyncResult<Value> {
+        match AssertUnwindSafe(next.run(request, sink)).catch_unwind().await {
+            Ok(result) => result,
+            Err(panic) => {
+                log::warn!("Error: Handler panicked on {} {}", request, panic_message(panic.as_ref()));
+
+                Err(Box::new(rpc::ErrorResponse::new(
+                    rpc::ErrorCode::InternalError,
+                    rpc::ErrorCode::InternalError.message(),
+                    None
+                )))
+            }
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic.downcast_ref::<String>().map(String::as_str).unwrap_or("(unknown)")
+    }
+}
+
+/// Checks the params of requests against those declared for their method,
+/// rejecting any that don't bind with Invalid params before they reach the
+/// responder. Methods without declared params are passed through unchecked.
+#[derive(Clone,Debug,Default)]
+pub struct Validate {
+    methods: HashMap<String,Params>
+}
+
+impl Validate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_method(mut self, method: impl ToString, params: Params) -> Self {
+        self.methods.insert(method.to_string(), params);
+
+        self
+    }
+}
+
+#[async_trait]
+impl Middleware for Validate {
+    async fn handle(&self, request: &rpc::Request, sink: &Sink, next: Next<'_>) -> AsyncResult<Value> {
+        if let Some(params) = self.methods.get(request.method().as_str()) {
+            params.bind(request.params())?;
+        }
+
+        next.run(request, sink).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::params::Param;
+
+    struct Echo;
+
+    #[async_trait]
+    impl Responder for Echo {
+        async fn respond(&mut self, request: &rpc::Request) -> AsyncResult<Value> {
+            match request.method().as_str() {
+                "panic" => panic!("Requested"),
+                "fail" => Err("Failed".into()),
+                _ => Ok(request.params().cloned().unwrap_or(Value::Null))
+            }
+        }
+    }
+
+    // Rewrites the params before passing the request on.
+    struct Wrap(&'static str);
+
+    #[async_trait]
+    impl Middleware for Wrap {
+        async fn handle(&self, request: &rpc::Request, sink: &Sink, next: Next<'_>) -> AsyncResult<Value> {
+            let params = json!({ self.0: request.params().cloned().unwrap_or(Value::Null) });
+
+            next.run(&rpc::Request::new(1, request.method(), Some(params)), sink).await
+        }
+    }
+
+    fn error_code(err: Box<dyn std::error::Error + Send + Sync>) -> i32 {
+        err.downcast::<rpc::ErrorResponse>().unwrap().code()
+    }
+
+    #[tokio::test]
+    async fn test_order() {
+        let mut responder = Echo.with_middleware(Wrap("inner")).with_middleware(Wrap("outer"));
+
+        assert_eq!(
+            responder.respond(&rpc::Request::new(1, "echo", Some(json!(1)))).await.unwrap(),
+            json!({ "inner": { "outer": 1 } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authorize() {
+        let mut responder = Echo.with_middleware(Authorize::new(|request: &rpc::Request| {
+            match request.meta().get("token").map(String::as_str) {
+                Some("secret") => Ok(()),
+                _ => Err(rpc::ErrorResponse::new(rpc::ErrorCode::ServerError(-32001), "Unauthorized", None))
+            }
+        }));
+
+        let request = rpc::Request::new(1, "echo", Some(json!(true)));
+
+        assert_eq!(error_code(responder.respond(&request).await.unwrap_err()), -32001);
+        assert_eq!(responder.respond(&request.clone().with_meta("token", "secret")).await.unwrap(), json!(true));
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let metrics = Metrics::new();
+        let mut responder = Echo.with_middleware(metrics.clone());
+
+        responder.respond(&rpc::Request::new(1, "echo", None)).await.unwrap();
+        responder.respond(&rpc::Request::new(2, "echo", None)).await.unwrap();
+        responder.respond(&rpc::Request::new(3, "fail", None)).await.unwrap_err();
+
+        assert_eq!(metrics.get("echo").map(|m| (m.calls, m.errors)), Some((2, 0)));
+        assert_eq!(metrics.get("fail").map(|m| (m.calls, m.errors)), Some((1, 1)));
+        assert_eq!(metrics.snapshot().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let mut responder = Echo.with_middleware(CatchPanic);
+
+        assert_eq!(error_code(responder.respond(&rpc::Request::new(1, "panic", None)).await.unwrap_err()), -32603);
+        assert_eq!(responder.respond(&rpc::Request::new(2, "echo", Some(json!(2)))).await.unwrap(), json!(2));
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let mut responder = Echo.with_middleware(
+            Validate::new().with_method("echo", Params::new(vec![ Param::required("name") ]))
+        );
+
+        assert_eq!(error_code(responder.respond(&rpc::Request::new(1, "echo", None)).await.unwrap_err()), -32602);
+        assert_eq!(responder.respond(&rpc::Request::new(2, "echo", Some(json!([ "a" ])))).await.unwrap(), json!([ "a" ]));
+        assert_eq!(responder.respond(&rpc::Request::new(3, "other", None)).await.unwrap(), json!(null));
+    }
+
+    #[cfg(feature="amqp")]
+    #[tokio::test]
+    async fn test_concurrent_worker() {
+        let metrics = Metrics::new();
+
+        let router = crate::Router::new(())
+            .with_route("echo", |_, request: rpc::Request| async move { Ok(request.params().cloned().unwrap_or(Value::Null)) })
+            .with_middleware(Logging)
+            .with_middleware(metrics.clone())
+            .with_middleware(CatchPanic);
+
+        let (worker, _terminator) = crate::amqp::Worker::new(router, "amqp://localhost:5672/%2f", "test", None, None).unwrap();
+
+        let mut worker = worker.with_concurrency(4);
+
+        // Each delivery in flight gets a clone, which shares the routes and
+        // the metrics with the rest.
+        let mut clone = worker.context().clone();
+
+        assert_eq!(clone.respond(&rpc::Request::new(1, "echo", Some(json!(1)))).await.unwrap(), json!(1));
+        assert_eq!(worker.context_mut().respond(&rpc::Request::new(2, "echo", Some(json!(2)))).await.unwrap(), json!(2));
+        assert_eq!(metrics.get("echo").map(|m| m.calls), Some(2));
+    }
+}
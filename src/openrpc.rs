@@ -0,0 +1,343 @@
+use std::marker::PhantomData;
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json,Value};
+
+use crate::params::{Param,Params};
+use crate::rpc::ErrorCode;
+
+/// The reserved method a worker answers with its service description.
+pub const DISCOVER_METHOD : &str = "rpc.discover";
+
+pub const OPENRPC_VERSION : &str = "1.2.6";
+
+/// An OpenRPC service description listing the methods a worker supports.
+#[derive(Clone,Debug,PartialEq,Deserialize,Serialize)]
+pub struct Document {
+    pub openrpc: String,
+    pub info: Info,
+    #[serde(default)]
+    pub methods: Vec<Method>
+}
+
+impl Document {
+    pub fn new(title: impl ToString, version: impl ToString) -> Self {
+        Self {
+            openrpc: OPENRPC_VERSION.to_string(),
+            info: Info {
+                title: title.to_string(),
+                version: version.to_string(),
+                description: None
+            },
+            methods: Vec::new()
+        }
+    }
+
+    pub fn with_description(mut self, description: impl ToString) -> Self {
+        self.info.description = Some(description.to_string());
+
+        self
+    }
+
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.methods.push(method);
+
+        self
+    }
+
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+}
+
+#[derive(Clone,Debug,PartialEq,Deserialize,Serialize)]
+pub struct Info {
+    pub title: String,
+    pub version: String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub description: Option<String>
+}
+
+#[derive(Clone,Debug,PartialEq,Deserialize,Serialize)]
+pub struct Method {
+    pub name: String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub params: Vec<ContentDescriptor>,
+    #[serde(default,rename="paramStructure",skip_serializing_if="ParamStructure::is_either")]
+    pub param_structure: ParamStructure,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub result: Option<ContentDescriptor>,
+    #[serde(default,skip_serializing_if="Vec::is_empty")]
+    pub errors: Vec<ErrorDefinition>
+}
+
+impl Method {
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            summary: None,
+            params: Vec::new(),
+            param_structure: ParamStructure::Either,
+            result: None,
+            errors: Vec::new()
+        }
+    }
+
+    pub fn with_summary(mut self, summary: impl ToString) -> Self {
+        self.summary = Some(summary.to_string());
+
+        self
+    }
+
+    pub fn with_param(mut self, param: impl Into<ContentDescriptor>) -> Self {
+        self.params.push(param.into());
+
+        self
+    }
+
+    /// Describes each of the declared params, in positional order.
+    pub fn with_params(mut self, params: &Params) -> Self {
+        self.params.extend(params.params().iter().map(ContentDescriptor::from));
+
+        self
+    }
+
+    pub fn with_param_structure(mut self, param_structure: ParamStructure) -> Self {
+        self.param_structure = param_structure;
+
+        self
+    }
+
+    pub fn with_result(mut self, result: impl Into<ContentDescriptor>) -> Self {
+        self.result = Some(result.into());
+
+        self
+    }
+
+    pub fn with_error(mut self, error: impl Into<ErrorDefinition>) -> Self {
+        self.errors.push(error.into());
+
+        self
+    }
+}
+
+/// Whether a method's params are to be given by name, by position, or either
+/// way, which is the default.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq,Deserialize,Serialize)]
+#[serde(rename_all="kebab-case")]
+pub enum ParamStructure {
+    ByName,
+    ByPosition,
+    #[default]
+    Either
+}
+
+impl ParamStructure {
+    pub fn is_either(&self) -> bool {
+        *self == Self::Either
+    }
+}
+
+/// Describes a param or result by name along with its JSON Schema.
+#[derive(Clone,Debug,PartialEq,Deserialize,Serialize)]
+pub struct ContentDescriptor {
+    pub name: String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default,skip_serializing_if="std::ops::Not::not")]
+    pub required: bool,
+    pub schema: Value
+}
+
+impl ContentDescriptor {
+    /// Creates an optional descriptor with the given schema.
+    pub fn new(name: impl ToString, schema: Value) -> Self {
+        Self {
+            name: name.to_string(),
+            summary: None,
+            required: false,
+            schema
+        }
+    }
+
+    pub fn with_summary(mut self, summary: impl ToString) -> Self {
+        self.summary = Some(summary.to_string());
+
+        self
+    }
+
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+
+        self
+    }
+
+    pub fn with_schema(mut self, schema: Value) -> Self {
+        self.schema = schema;
+
+        self
+    }
+
+    /// Creates an optional descriptor with the JSON Schema of type T.
+    #[cfg(feature="schema")]
+    pub fn of<T>(name: impl ToString) -> Self where T : schemars::JsonSchema {
+        Self::new(name, schema_of::<T>())
+    }
+}
+
+/// Generates the JSON Schema for type T, without the $schema marker as it is
+/// embedded within a document.
+#[cfg(feature="schema")]
+pub fn schema_of<T>() -> Value where T : schemars::JsonSchema {
+    let mut schema = serde_json::to_value(schemars::schema_for!(T)).unwrap_or_else(|_| json!({ }));
+
+    if let Some(object) = schema.as_object_mut() {
+        object.remove("$schema");
+    }
+
+    schema
+}
+
+/// Probes for the JSON Schema of type T, for use by generated code that can't
+/// know if T implements JsonSchema or if the schema feature is enabled. The
+/// schema is found by calling (&SchemaProbe::<T>::new()).schema() with both
+/// DescribedSchema and UndescribedSchema in scope, where the former applies
+/// only if possible and the latter falls back to an unconstrained schema.
+#[doc(hidden)]
+pub struct SchemaProbe<T>(PhantomData<T>);
+
+impl<T> SchemaProbe<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait DescribedSchema {
+    fn schema(&self) -> Value;
+}
+
+#[cfg(feature="schema")]
+impl<T> DescribedSchema for SchemaProbe<T> where T : schemars::JsonSchema {
+    fn schema(&self) -> Value {
+        schema_of::<T>()
+    }
+}
+
+#[doc(hidden)]
+pub trait UndescribedSchema {
+    fn schema(&self) -> Value;
+}
+
+impl<T> UndescribedSchema for &SchemaProbe<T> {
+    fn schema(&self) -> Value {
+        json!({ })
+    }
+}
+
+/// Params are described with an unconstrained schema, carrying the default
+/// if there is one. A more specific schema can be set with with_schema.
+impl From<&Param> for ContentDescriptor {
+    fn from(param: &Param) -> Self {
+        let schema = match param.default() {
+            Some(default) => json!({ "default": default }),
+            None => json!({ })
+        };
+
+        Self::new(param.name(), schema).with_required(param.is_required())
+    }
+}
+
+#[derive(Clone,Debug,Eq,PartialEq,Deserialize,Serialize)]
+pub struct ErrorDefinition {
+    pub code: i32,
+    pub message: String,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub data: Option<Value>
+}
+
+impl ErrorDefinition {
+    pub fn new(code: impl Into<ErrorCode>, message: impl ToString) -> Self {
+        Self {
+            code: code.into().code(),
+            message: message.to_string(),
+            data: None
+        }
+    }
+}
+
+impl From<ErrorCode> for ErrorDefinition {
+    fn from(code: ErrorCode) -> Self {
+        Self::new(code, code.message())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_document() {
+        let document = Document::new("example", "1.0.0").with_method(
+            Method::new("echo")
+                .with_params(&Params::new(vec![ Param::required("text"), Param::with_default("count", json!(1)) ]))
+                .with_result(ContentDescriptor::new("text", json!({ "type": "string" })))
+                .with_error(ErrorCode::InvalidParams)
+        );
+
+        assert_eq!(
+            serde_json::to_value(&document).unwrap(),
+            json!({
+                "openrpc": "1.2.6",
+                "info": { "title": "example", "version": "1.0.0" },
+                "methods": [
+                    {
+                        "name": "echo",
+                        "params": [
+                            { "name": "text", "required": true, "schema": { } },
+                            { "name": "count", "schema": { "default": 1 } }
+                        ],
+                        "result": { "name": "text", "schema": { "type": "string" } },
+                        "errors": [ { "code": -32602, "message": "Invalid params" } ]
+                    }
+                ]
+            })
+        );
+
+        let decoded : Document = serde_json::from_value(serde_json::to_value(&document).unwrap()).unwrap();
+
+        assert_eq!(decoded, document);
+        assert!(decoded.method("echo").is_some());
+    }
+
+    #[test]
+    fn test_param_structure() {
+        let method = Method::new("echo").with_param_structure(ParamStructure::ByPosition);
+
+        assert_eq!(
+            serde_json::to_value(&method).unwrap(),
+            json!({ "name": "echo", "params": [ ], "paramStructure": "by-position" })
+        );
+
+        let decoded : Method = serde_json::from_value(json!({ "name": "echo" })).unwrap();
+
+        assert_eq!(decoded.param_structure, ParamStructure::Either);
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn test_schema_probe() {
+        struct Opaque;
+
+        assert_eq!((&SchemaProbe::<Opaque>::new()).schema(), json!({ }));
+
+        #[cfg(feature="schema")]
+        {
+            assert_eq!((&SchemaProbe::<String>::new()).schema(), json!({ "title": "String", "type": "string" }));
+            assert_eq!(ContentDescriptor::of::<u8>("byte").schema["type"], "integer");
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use serde::de::DeserializeOwned;
+use serde_json::{json,Map,Value};
+
+use crate::rpc::{ErrorCode,ErrorResponse};
+
+/// A single declared method parameter.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Param {
+    name: String,
+    required: bool,
+    default: Option<Value>
+}
+
+impl Param {
+    /// A parameter which must be supplied by the caller.
+    pub fn required(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            required: true,
+            default: None
+        }
+    }
+
+    /// A parameter which may be omitted, in which case it is left out of the
+    /// bound params entirely.
+    pub fn optional(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            required: false,
+            default: None
+        }
+    }
+
+    /// A parameter which takes on the given value when omitted.
+    pub fn with_default(name: impl ToString, default: Value) -> Self {
+        Self {
+            name: name.to_string(),
+            required: false,
+            default: Some(default)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    pub fn default(&self) -> Option<&Value> {
+        self.default.as_ref()
+    }
+}
+
+/// The parameters a method accepts, in positional order. Request params given
+/// either as a positional array or as a named object are bound onto these,
+/// producing a named object suitable for deserializing into a struct.
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct Params {
+    params: Vec<Param>
+}
+
+impl Params {
+    pub fn new(params: Vec<Param>) -> Self {
+        Self { params }
+    }
+
+    pub fn with(mut self, param: Param) -> Self {
+        self.params.push(param);
+
+        self
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|p| p.name == name)
+    }
+
+    /// Binds the given params to their declared names. A bare value which is
+    /// neither an array nor an object is treated as the sole positional
+    /// argument. Missing optional params are filled in with their defaults.
+    pub fn bind(&self, params: Option<&Value>) -> Result<Map<String,Value>, ErrorResponse> {
+        let mut bound = Map::new();
+
+        match params {
+            None | Some(Value::Null) => { },
+            Some(Value::Array(items)) => {
+                if items.len() > self.params.len() {
+                    return Err(invalid_params(
+                        format!("Invalid params, expected at most {} but received {}", self.params.len(), items.len()),
+                        Some(json!({ "expected": self.params.len(), "received": items.len() }))
+                    ));
+                }
+
+                for (param, value) in self.params.iter().zip(items) {
+                    bound.insert(param.name.clone(), value.clone());
+                }
+            },
+            Some(Value::Object(named)) => {
+                for (name, value) in named {
+                    if self.get(name).is_none() {
+                        return Err(invalid_params(
+                            format!("Invalid params, unknown parameter {:?}", name),
+                            param_data(name)
+                        ));
+                    }
+
+                    bound.insert(name.clone(), value.clone());
+                }
+            },
+            Some(value) => {
+                match self.params.first() {
+                    Some(param) => {
+                        bound.insert(param.name.clone(), value.clone());
+                    },
+                    None => {
+                        return Err(invalid_params("Invalid params, none expected", Some(json!({ "expected": 0, "received": 1 }))));
+                    }
+                }
+            }
+        }
+
+        for param in &self.params {
+            if bound.contains_key(&param.name) {
+                continue;
+            }
+
+            if let Some(default) = &param.default {
+                bound.insert(param.name.clone(), default.clone());
+            }
+            else if param.required {
+                return Err(invalid_params(
+                    format!("Invalid params, missing required parameter {:?}", param.name),
+                    param_data(&param.name)
+                ));
+            }
+        }
+
+        Ok(bound)
+    }
+
+    /// Binds the given params and deserializes them into the given type. Any
+    /// deserialization failure names the parameter that caused it.
+    pub fn bind_as<T>(&self, params: Option<&Value>) -> Result<T, ErrorResponse> where T : DeserializeOwned {
+        let bound = Value::Object(self.bind(params)?);
+
+        serde_path_to_error::deserialize(bound).map_err(|err| {
+            let path = err.path().to_string();
+
+            // The top-level segment of the path is the parameter name, which
+            // is empty if the failure isn't within any one parameter.
+            let name = path.split(['.', '[']).next().unwrap_or_default();
+
+            invalid_params(
+                format!("Invalid params, parameter {:?}: {}", path, err.inner()),
+                if name.is_empty() { None } else { param_data(name) }
+            )
+        })
+    }
+}
+
+impl From<Vec<Param>> for Params {
+    fn from(params: Vec<Param>) -> Self {
+        Self::new(params)
+    }
+}
+
+impl FromIterator<Param> for Params {
+    fn from_iter<I: IntoIterator<Item = Param>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+// The data carries the offending parameter's name, or for count problems,
+// the number expected and received.
+fn invalid_params(message: impl ToString, data: Option<Value>) -> ErrorResponse {
+    ErrorResponse::new(ErrorCode::InvalidParams, message, data)
+}
+
+fn param_data(name: &str) -> Option<Value> {
+    Some(json!({ "param": name }))
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug,Deserialize,PartialEq)]
+    struct Example {
+        name: String,
+        count: i64,
+        #[serde(default)]
+        label: Option<String>
+    }
+
+    fn example_params() -> Params {
+        Params::new(vec![
+            Param::required("name"),
+            Param::with_default("count", json!(1)),
+            Param::optional("label")
+        ])
+    }
+
+    #[test]
+    fn test_bind_positional() {
+        let params = example_params();
+
+        assert_eq!(
+            params.bind_as::<Example>(Some(&json!([ "test", 3, "x" ]))).unwrap(),
+            Example { name: "test".into(), count: 3, label: Some("x".into()) }
+        );
+
+        assert_eq!(
+            params.bind_as::<Example>(Some(&json!([ "test" ]))).unwrap(),
+            Example { name: "test".into(), count: 1, label: None }
+        );
+
+        assert_eq!(
+            params.bind_as::<Example>(Some(&json!("test"))).unwrap(),
+            Example { name: "test".into(), count: 1, label: None }
+        );
+    }
+
+    #[test]
+    fn test_bind_named() {
+        let params = example_params();
+
+        assert_eq!(
+            params.bind_as::<Example>(Some(&json!({ "label": "x", "name": "test" }))).unwrap(),
+            Example { name: "test".into(), count: 1, label: Some("x".into()) }
+        );
+    }
+
+    #[test]
+    fn test_bind_errors() {
+        let params = example_params();
+
+        let error = params.bind(None).unwrap_err();
+
+        assert!(error.is_invalid_params());
+        assert_eq!(error.data(), Some(&json!({ "param": "name" })));
+
+        let error = params.bind(Some(&json!([ "test", 1, "x", true ]))).unwrap_err();
+
+        assert!(error.is_invalid_params());
+        assert_eq!(error.data(), Some(&json!({ "expected": 3, "received": 4 })));
+
+        let error = params.bind(Some(&json!({ "name": "test", "size": 2 }))).unwrap_err();
+
+        assert_eq!(error.data(), Some(&json!({ "param": "size" })));
+
+        let error = params.bind_as::<Example>(Some(&json!([ "test", "many" ]))).unwrap_err();
+
+        assert!(error.is_invalid_params());
+        assert_eq!(error.data(), Some(&json!({ "param": "count" })));
+        assert!(error.message().contains("\"count\""));
+
+        let error = Params::new(vec![ ]).bind(Some(&json!("test"))).unwrap_err();
+
+        assert_eq!(error.data(), Some(&json!({ "expected": 0, "received": 1 })));
+
+        let error = Params::new(vec![ ]).bind_as::<Vec<Value>>(None).unwrap_err();
+
+        assert!(error.is_invalid_params());
+        assert_eq!(error.data(), None);
+    }
+}
@@ -1,7 +1,10 @@
 use async_trait::async_trait;
 use serde_json::Value;
 
+use super::middleware::{Layered,Middleware};
+use super::openrpc;
 use super::rpc;
+use super::sink::Sink;
 
 use crate::AsyncResult;
 
@@ -11,5 +14,23 @@ pub trait Responder : Send + Sized + Sync + 'static {
         request
     }
 
+    /// Describes the methods this Responder supports. When provided, the
+    /// reserved rpc.discover method is answered automatically.
+    fn describe(&self) -> Option<openrpc::Document> {
+        None
+    }
+
     async fn respond(&mut self, request: &rpc::Request) -> AsyncResult<Value>;
+
+    /// Responds to a request, where partial results and progress can be sent
+    /// to the caller through the sink before the final result is returned.
+    /// By default the sink goes unused.
+    async fn respond_streaming(&mut self, request: &rpc::Request, _sink: &Sink) -> AsyncResult<Value> {
+        self.respond(request).await
+    }
+
+    /// Wraps this Responder in the given middleware.
+    fn with_middleware<M>(self, middleware: M) -> Layered<Self,M> where M : Middleware {
+        Layered::new(self, middleware)
+    }
 }
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde_json::{json,Value};
+
+use crate::AsyncResult;
+use crate::openrpc;
+use crate::Responder;
+use crate::rpc;
+use crate::sink::Sink;
+
+type Handler<S> = Arc<dyn Fn(Arc<S>, rpc::Request, Sink) -> BoxFuture<'static, AsyncResult<Value>> + Send + Sync>;
+
+struct Route<S> {
+    handler: Handler<S>,
+    description: openrpc::Method
+}
+
+impl<S> Clone for Route<S> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            description: self.description.clone()
+        }
+    }
+}
+
+/// A Responder that dispatches each request to the handler registered for
+/// its method, answering any others with Method not found. Handlers are
+/// async functions or closures given the shared state along with the
+/// request, and state that changes needs its own synchronization, such as a
+/// Mutex or atomics.
+///
+/// When given info with with_info, the registered methods are described in
+/// response to rpc.discover.
+///
+/// Clones share the same state and routes, so a Worker can handle requests
+/// concurrently with one for each.
+pub struct Router<S> where S : Send + Sync + 'static {
+    state: Arc<S>,
+    routes: Arc<BTreeMap<String,Route<S>>>,
+    info: Option<(String,String)>
+}
+
+impl<S> Router<S> where S : Send + Sync + 'static {
+    pub fn new(state: S) -> Self {
+        Self {
+            state: Arc::new(state),
+            routes: Arc::new(BTreeMap::new()),
+            info: None
+        }
+    }
+
+    pub fn state(&self) -> &Arc<S> {
+        &self.state
+    }
+
+    /// Registers the handler for a method, replacing any already registered.
+    pub fn with_route<F,Fut>(self, method: impl ToString, handler: F) -> Self where F : Fn(Arc<S>, rpc::Request) -> Fut + Send + Sync + 'static, Fut : Future<Output = AsyncResult<Value>> + Send + 'static {
+        self.with_streaming_route(method, move |state, request, _sink| handler(state, request))
+    }
+
+    /// As with with_route, though the handler is also given the sink for
+    /// sending partial results, progress, or starting subscriptions.
+    pub fn with_streaming_route<F,Fut>(mut self, method: impl ToString, handler: F) -> Self where F : Fn(Arc<S>, rpc::Request, Sink) -> Fut + Send + Sync + 'static, Fut : Future<Output = AsyncResult<Value>> + Send + 'static {
+        let method = method.to_string();
+
+        let route = Route {
+            handler: Arc::new(move |state, request, sink| Box::pin(handler(state, request, sink))),
+            description: openrpc::Method::new(&method)
+                .with_result(openrpc::ContentDescriptor::new("result", json!({ })))
+        };
+
+        Arc::make_mut(&mut self.routes).insert(method, route);
+
+        self
+    }
+
+    /// Describes a registered method in more detail for rpc.discover, as by
+    /// default only its name and an unconstrained result are listed. This is
+    /// ignored if no such method is registered.
+    pub fn with_route_description(mut self, description: openrpc::Method) -> Self {
+        match Arc::make_mut(&mut self.routes).get_mut(&description.name) {
+            Some(route) => {
+                route.description = description;
+            },
+            None => {
+                log::warn!("Error: Description of unregistered method {}", description.name);
+            }
+        }
+
+        self
+    }
+
+    /// Enables rpc.discover, describing the service with the given title and
+    /// version.
+    pub fn with_info(mut self, title: impl ToString, version: impl ToString) -> Self {
+        self.info = Some((title.to_string(), version.to_string()));
+
+        self
+    }
+
+    /// Returns the names of the registered methods in sorted order.
+    pub fn methods(&self) -> Vec<&str> {
+        self.routes.keys().map(String::as_str).collect()
+    }
+
+    pub fn contains(&self, method: &str) -> bool {
+        self.routes.contains_key(method)
+    }
+
+    /// Dispatches a request to its handler without needing exclusive access
+    /// to the router.
+    pub async fn dispatch(&self, request: &rpc::Request, sink: &Sink) -> AsyncResult<Value> {
+        match self.routes.get(request.method().as_str()) {
+            Some(route) => (route.handler)(self.state.clone(), request.clone(), sink.clone()).await,
+            None => {
+                Err(Box::new(rpc::ErrorResponse::new(
+                    rpc::ErrorCode::MethodNotFound,
+                    rpc::ErrorCode::MethodNotFound.message(),
+                    Some(json!({ "method": request.method() }))
+                )))
+            }
+        }
+    }
+}
+
+impl<S> Clone for Router<S> where S : Send + Sync + 'static {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            routes: self.routes.clone(),
+            info: self.info.clone()
+        }
+    }
+}
+
+impl<S> Default for Router<S> where S : Default + Send + Sync + 'static {
+    fn default() -> Self {
+        Self::new(S::default())
+    }
+}
+
+#[async_trait]
+impl<S> Responder for Router<S> where S : Send + Sync + 'static {
+    fn describe(&self) -> Option<openrpc::Document> {
+        let (title, version) = self.info.as_ref()?;
+
+        Some(
+            self.routes.values().fold(
+                openrpc::Document::new(title, version),
+                |document, route| document.with_method(route.description.clone())
+            )
+        )
+    }
+
+    async fn respond(&mut self, request: &rpc::Request) -> AsyncResult<Value> {
+        self.dispatch(request, &Sink::discard()).await
+    }
+
+    async fn respond_streaming(&mut self, request: &rpc::Request, sink: &Sink) -> AsyncResult<Value> {
+        self.dispatch(request, sink).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize,Ordering};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter {
+        calls: AtomicUsize
+    }
+
+    async fn count(state: Arc<Counter>, _request: rpc::Request) -> AsyncResult<Value> {
+        Ok(json!(state.calls.fetch_add(1, Ordering::SeqCst) + 1))
+    }
+
+    fn router() -> Router<Counter> {
+        Router::default()
+            .with_route("count", count)
+            .with_route("echo", |_state, request: rpc::Request| async move {
+                Ok(request.params().cloned().unwrap_or(Value::Null))
+            })
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let mut router = router();
+
+        assert_eq!(router.respond(&rpc::Request::new(1, "count", None)).await.unwrap(), json!(1));
+        assert_eq!(router.respond(&rpc::Request::new(2, "count", None)).await.unwrap(), json!(2));
+        assert_eq!(router.respond(&rpc::Request::new(3, "echo", Some(json!([ "test" ])))).await.unwrap(), json!([ "test" ]));
+
+        // Clones share the same state.
+        let mut clone = router.clone();
+
+        assert_eq!(clone.respond(&rpc::Request::new(4, "count", None)).await.unwrap(), json!(3));
+        assert_eq!(router.state().calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_method_not_found() {
+        let mut router = router();
+
+        let request = rpc::Request::new(1, "missing", None);
+        let response = rpc::Response::error_from(&request, router.respond(&request).await.unwrap_err());
+
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": { "code": -32601, "message": "Method not found", "data": { "method": "missing" } }
+            })
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let router = router();
+
+        assert_eq!(router.methods(), vec![ "count", "echo" ]);
+        assert!(router.contains("echo"));
+        assert!(router.describe().is_none());
+
+        let router = router
+            .with_info("example", "1.0.0")
+            .with_route_description(openrpc::Method::new("echo").with_summary("Returns the params as given"));
+
+        let document = router.describe().unwrap();
+
+        assert_eq!(document.methods.len(), 2);
+        assert!(document.method("count").unwrap().result.is_some());
+        assert_eq!(document.method("echo").unwrap().summary.as_deref(), Some("Returns the params as given"));
+    }
+}
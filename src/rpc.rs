@@ -1,52 +1,317 @@
-use std::convert::TryFrom;
+// Errors are reported as ready-to-send Response values throughout.
+#![allow(clippy::result_large_err)]
+
+use std::collections::BTreeMap;
 use std::fmt::{self,Display};
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+use std::time::{Duration,SystemTime};
 
-use lapin::BasicProperties;
-use lapin::message::Delivery;
 use serde::{Serialize, Deserialize};
-use serde_json::json;
-use serde_json::Value;
-use serde::ser::{SerializeStruct,Serializer};
+use serde::de::DeserializeOwned;
+use serde_json::{Map,Value};
+use serde::ser::{SerializeMap,Serializer};
 use serde::de::{self,Deserializer,Visitor,SeqAccess,MapAccess};
+use uuid::Uuid;
+
+use crate::AsyncResult;
+use crate::codec::{self,Codec};
+use crate::context::RequestContext;
+use crate::params::Params;
+
+/// A JSON-RPC request id, preserved exactly as the caller sent it so replies
+/// echo the same type.
+#[derive(Clone,Debug,Eq,Hash,PartialEq,Deserialize,Serialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(serde_json::Number),
+    String(String),
+    Null
+}
 
-#[derive(Clone,Debug,Eq,PartialEq)]
+impl Id {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s.as_str()),
+            _ => None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Number(n) => n.as_i64(),
+            _ => None
+        }
+    }
+}
+
+impl Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{}", s),
+            Self::Null => write!(f, "null")
+        }
+    }
+}
+
+impl From<String> for Id {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<&String> for Id {
+    fn from(s: &String) -> Self {
+        Self::String(s.clone())
+    }
+}
+
+impl From<&str> for Id {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<i64> for Id {
+    fn from(n: i64) -> Self {
+        Self::Number(n.into())
+    }
+}
+
+impl From<u64> for Id {
+    fn from(n: u64) -> Self {
+        Self::Number(n.into())
+    }
+}
+
+impl From<i32> for Id {
+    fn from(n: i32) -> Self {
+        Self::Number(n.into())
+    }
+}
+
+impl From<u32> for Id {
+    fn from(n: u32) -> Self {
+        Self::Number(n.into())
+    }
+}
+
+impl From<serde_json::Number> for Id {
+    fn from(n: serde_json::Number) -> Self {
+        Self::Number(n)
+    }
+}
+
+impl From<Uuid> for Id {
+    fn from(uuid: Uuid) -> Self {
+        Self::String(uuid.to_string())
+    }
+}
+
+/// How closely incoming messages are held to the JSON-RPC 2.0 specification.
+/// In either mode the `jsonrpc` member must be present and equal to `"2.0"`.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub enum Strictness {
+    /// Unknown top-level members are preserved as extensions.
+    #[default]
+    Lenient,
+    /// Unknown top-level members are rejected as invalid, as are content
+    /// types that aren't recognised rather than being presumed to be JSON.
+    Strict
+}
+
+/// Out-of-band metadata such as caller identity or trace ids. This is never
+/// part of the JSON-RPC payload, and is instead carried by the transport.
+pub type Meta = BTreeMap<String,String>;
+
+/// The notification a caller sends to abandon a request that is still being
+/// processed, with the id of that request as the only param.
+pub const CANCEL_METHOD : &str = "$/cancelRequest";
+
+/// The notification carrying a partial result for a request ahead of its
+/// final response.
+pub const PARTIAL_RESULT_METHOD : &str = "$/partialResult";
+
+/// The notification reporting progress on a request ahead of its final
+/// response.
+pub const PROGRESS_METHOD : &str = "$/progress";
+
+/// The notification carrying an event for a subscription, sent to the
+/// subscriber until they unsubscribe or go away.
+pub const SUBSCRIPTION_METHOD : &str = "$/subscription";
+
+const REQUEST_MEMBERS: &[&str] = &[ "jsonrpc", "id", "method", "params" ];
+const RESPONSE_MEMBERS: &[&str] = &[ "jsonrpc", "id", "result", "error" ];
+
+#[derive(Clone,Debug)]
 pub struct Request {
-    id : String,
+    id : Option<Id>,
     method : String,
     params : Option<Value>,
-    reply_to : bool
+    reply_to : bool,
+    extensions : Map<String,Value>,
+    meta : Meta,
+    deadline : Option<SystemTime>,
+    context : RequestContext
 }
 
 impl Request {
-    pub fn new(id: impl ToString, method: impl ToString, params: Option<Value>) -> Self {
+    pub fn new(id: impl Into<Id>, method: impl ToString, params: Option<Value>) -> Self {
         Self {
-            id: id.to_string(),
+            id: Some(id.into()),
             method: method.to_string(),
             params,
-            reply_to: true
+            reply_to: true,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
         }
     }
 
-    pub fn new_serialize(id: impl ToString, method: impl ToString, params: Option<impl Into<Value>>) -> Self {
+    pub fn new_serialize(id: impl Into<Id>, method: impl ToString, params: Option<impl Into<Value>>) -> Self {
         Self {
-            id: id.to_string(),
+            id: Some(id.into()),
             method: method.to_string(),
             params: params.map(|p| p.into()),
-            reply_to: true
+            reply_to: true,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
+        }
+    }
+
+    pub fn new_noreply(id: impl Into<Id>, method: impl ToString, params: Option<Value>) -> Self {
+        Self {
+            id: Some(id.into()),
+            method: method.to_string(),
+            params,
+            reply_to: false,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
         }
     }
 
-    pub fn new_noreply(id: impl ToString, method: impl ToString, params: Option<Value>) -> Self {
+    /// Creates a notification, a request without an id that never receives
+    /// a reply.
+    pub fn new_notification(method: impl ToString, params: Option<Value>) -> Self {
         Self {
-            id: id.to_string(),
+            id: None,
             method: method.to_string(),
             params,
-            reply_to: false
+            reply_to: false,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
+        }
+    }
+
+    /// Creates the notification that cancels the request with the given id,
+    /// sent by the caller whose replies go to reply_to. Ids are only unique
+    /// per caller, so both are needed to tell which request is meant.
+    pub fn new_cancel(id: &Id, reply_to: impl ToString) -> Self {
+        Self::new_notification(CANCEL_METHOD, Some(serde_json::json!({ "id": id, "replyTo": reply_to.to_string() })))
+    }
+
+    /// Creates the notification carrying a partial result, where seq counts
+    /// up from zero so callers can tell if any went missing.
+    pub fn new_partial_result(id: &Id, seq: u64, value: Value) -> Self {
+        PartialResult { id: id.clone(), seq, value }.into()
+    }
+
+    /// Creates the notification reporting progress on the request with the
+    /// given id.
+    pub fn new_progress(id: &Id, progress: &Progress) -> Self {
+        let mut params = Map::new();
+
+        params.insert("id".to_string(), serde_json::to_value(id).unwrap_or(Value::Null));
+
+        if let Ok(Value::Object(fields)) = serde_json::to_value(progress) {
+            params.extend(fields);
+        }
+
+        Self::new_notification(PROGRESS_METHOD, Some(Value::Object(params)))
+    }
+
+    /// Creates the notification carrying an event for the subscription with
+    /// the given id.
+    pub fn new_subscription_event(subscription: impl ToString, result: Value) -> Self {
+        SubscriptionEvent { subscription: subscription.to_string(), result }.into()
+    }
+
+    pub fn id(&self) -> Option<&Id> {
+        self.id.as_ref()
+    }
+
+    /// Returns the id of the request being cancelled if this is a
+    /// cancellation notification.
+    pub fn cancel_target(&self) -> Option<Id> {
+        if self.method != CANCEL_METHOD {
+            return None;
+        }
+
+        match self.params.as_ref()?.get("id")? {
+            Value::Null => None,
+            id => serde_json::from_value(id.clone()).ok()
+        }
+    }
+
+    /// Returns where replies to the request being cancelled go, if this is a
+    /// cancellation notification that says.
+    pub fn cancel_reply_to(&self) -> Option<&str> {
+        if self.method != CANCEL_METHOD {
+            return None;
+        }
+
+        self.params.as_ref()?.get("replyTo")?.as_str()
+    }
+
+    /// Returns the partial result if this is a partial result notification.
+    pub fn partial_result(&self) -> Option<PartialResult> {
+        if self.method != PARTIAL_RESULT_METHOD {
+            return None;
+        }
+
+        serde_json::from_value(self.params.clone()?).ok()
+    }
+
+    /// Returns the id of the request being reported on along with the
+    /// progress if this is a progress notification.
+    pub fn progress(&self) -> Option<(Id, Progress)> {
+        if self.method != PROGRESS_METHOD {
+            return None;
+        }
+
+        let params = self.params.as_ref()?;
+
+        let id = match params.get("id")? {
+            Value::Null => return None,
+            id => serde_json::from_value(id.clone()).ok()?
+        };
+
+        Some((id, serde_json::from_value(params.clone()).ok()?))
+    }
+
+    /// Returns the event if this is a subscription notification.
+    pub fn subscription_event(&self) -> Option<SubscriptionEvent> {
+        if self.method != SUBSCRIPTION_METHOD {
+            return None;
         }
+
+        serde_json::from_value(self.params.clone()?).ok()
     }
 
-    pub fn id(&self) -> &String {
-        &self.id
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
     }
 
     pub fn method(&self) -> &String {
@@ -61,28 +326,104 @@ impl Request {
         self.reply_to
     }
 
-    pub fn properties(&self, reply_to: &str) -> BasicProperties {
-        let properties = BasicProperties::default().with_content_type("application/json".into());
+    /// Deserializes the params into the given type, where missing params are
+    /// treated as null. Any failure is reported as an Invalid params error
+    /// which can be returned directly from a Responder.
+    pub fn params_as<T>(&self) -> Result<T, ErrorResponse> where T : DeserializeOwned {
+        T::deserialize(self.params.as_ref().unwrap_or(&Value::Null)).map_err(|err| {
+            ErrorResponse::new(ErrorCode::InvalidParams, ErrorCode::InvalidParams.message(), Some(Value::String(err.to_string())))
+        })
+    }
 
-        if self.reply_to() {
-            properties.with_reply_to(reply_to.into())
-        }
-        else {
-            properties
-        }
+    /// Binds positional or named params onto the declared parameters before
+    /// deserializing them into the given type.
+    pub fn bind_params<T>(&self, params: &Params) -> Result<T, ErrorResponse> where T : DeserializeOwned {
+        params.bind_as(self.params.as_ref())
+    }
+
+    /// Top-level members that aren't part of the JSON-RPC 2.0 specification.
+    /// These are preserved when decoding in lenient mode and re-emitted when
+    /// serialized.
+    pub fn extensions(&self) -> &Map<String,Value> {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Map<String,Value> {
+        &mut self.extensions
+    }
+
+    pub fn with_extension(mut self, key: impl ToString, value: Value) -> Self {
+        self.extensions.insert(key.to_string(), value);
+
+        self
+    }
+
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    pub fn meta_mut(&mut self) -> &mut Meta {
+        &mut self.meta
+    }
+
+    pub fn with_meta(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.meta.insert(key.to_string(), value.to_string());
+
+        self
+    }
+
+    /// The time after which the caller is no longer waiting for a reply. Like
+    /// meta, this is carried by the transport rather than the payload.
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.deadline
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<SystemTime>) {
+        self.deadline = deadline;
+    }
+
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+
+        self
     }
 
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(SystemTime::now() + timeout)
+    }
+
+    /// Returns how long remains before the deadline, which is zero if it has
+    /// already passed, or None if there is no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// How the request was received, as filled in by the transport. This is
+    /// never sent, and is empty for requests that haven't been received.
+    pub fn context(&self) -> &RequestContext {
+        &self.context
+    }
+
+    pub fn set_context(&mut self, context: RequestContext) {
+        self.context = context;
+    }
+
+    pub fn with_context(mut self, context: RequestContext) -> Self {
+        self.context = context;
+
+        self
+    }
+
+    /// Unwraps params consisting of a single-element array. Params::bind
+    /// handles this along with the more general cases.
     pub fn shed_single_outer_array(mut self) -> Self {
-        if let Some(value) = &mut self.params {
-            if let Some(array) = value.as_array_mut() {
-                if array.len() == 1 {
-                    return Self {
-                        id: self.id,
-                        method: self.method,
-                        params: array.pop(),
-                        reply_to: self.reply_to
-                    };
-                }
+        if let Some(Value::Array(array)) = &mut self.params {
+            if array.len() == 1 {
+                self.params = array.pop();
             }
         }
 
@@ -90,13 +431,97 @@ impl Request {
     }
 }
 
-impl Display for Request {
+/// One piece of a streamed result, sent as a notification tied to the id of
+/// the request it belongs to.
+#[derive(Clone,Debug,Eq,PartialEq,Deserialize,Serialize)]
+pub struct PartialResult {
+    pub id: Id,
+    pub seq: u64,
+    pub value: Value
+}
+
+/// An event for a subscription, identified by the id returned from the call
+/// that created it.
+#[derive(Clone,Debug,Eq,PartialEq,Deserialize,Serialize)]
+pub struct SubscriptionEvent {
+    pub subscription: String,
+    pub result: Value
+}
+
+impl From<SubscriptionEvent> for Request {
+    fn from(event: SubscriptionEvent) -> Self {
+        Request::new_notification(SUBSCRIPTION_METHOD, serde_json::to_value(event).ok())
+    }
+}
+
+/// How far along a request is, as a percentage from 0 to 100, a message, or
+/// both.
+#[derive(Clone,Debug,Default,PartialEq,Deserialize,Serialize)]
+pub struct Progress {
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub percentage: Option<f64>,
+    #[serde(default,skip_serializing_if="Option::is_none")]
+    pub message: Option<String>
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_percentage(mut self, percentage: f64) -> Self {
+        self.percentage = Some(percentage);
+
+        self
+    }
+
+    pub fn with_message(mut self, message: impl ToString) -> Self {
+        self.message = Some(message.to_string());
+
+        self
+    }
+}
+
+impl Display for Progress {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.params.is_some() {
-            write!(f, "{}: {}({:?})", &self.id, &self.method, &self.params)
+        match (self.percentage, &self.message) {
+            (Some(percentage), Some(message)) => write!(f, "{:.0}% {}", percentage, message),
+            (Some(percentage), None) => write!(f, "{:.0}%", percentage),
+            (None, Some(message)) => write!(f, "{}", message),
+            (None, None) => Ok(())
         }
-        else {
-            write!(f, "{}: {}", &self.id, &self.method)
+    }
+}
+
+impl From<PartialResult> for Request {
+    fn from(partial: PartialResult) -> Self {
+        Request::new_notification(PARTIAL_RESULT_METHOD, serde_json::to_value(partial).ok())
+    }
+}
+
+/// Requests are compared by content alone, so the context describing how
+/// each was received is ignored.
+impl PartialEq for Request {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id &&
+            self.method == other.method &&
+            self.params == other.params &&
+            self.reply_to == other.reply_to &&
+            self.extensions == other.extensions &&
+            self.meta == other.meta &&
+            self.deadline == other.deadline
+    }
+}
+
+impl Eq for Request { }
+
+impl Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.id, &self.params) {
+            (Some(id), Some(params)) => write!(f, "{}: {}({:?})", id, &self.method, params),
+            (Some(id), None) => write!(f, "{}: {}", id, &self.method),
+            (None, Some(params)) => write!(f, "-: {}({:?})", &self.method, params),
+            (None, None) => write!(f, "-: {}", &self.method)
         }
     }
 }
@@ -106,95 +531,112 @@ impl Serialize for Request {
     where
         S: Serializer,
     {
-        match &self.params {
-            Some(params) => json!({
-                "jsonrpc": "2.0",
-                "id": self.id,
-                "method": &self.method,
-                "params": params
-            }),
-            None => json!({
-                "jsonrpc": "2.0",
-                "id": self.id,
-                "method": &self.method
-            })
-        }.serialize(serializer)
-    }
-}
-
-impl TryFrom<&Delivery> for Request {
-    type Error = Response;
-
-    fn try_from(delivery: &Delivery) -> Result<Self, Response> {
-        match std::str::from_utf8(&delivery.data) {
-            Ok(s) => {
-                match serde_json::from_str::<Value>(s) {
-                    Ok(v) => {
-                        match &v["jsonrpc"] {
-                            Value::String(ver) => {
-                                match ver.as_str() {
-                                    "2.0" => {
-                                        match serde_json::from_str::<Request>(s) {
-                                            Ok(request) => Ok(request),
-                                            Err(err) => {
-                                                log::warn!("Error: JSON-RPC deserialization error {:?}", err);
-
-                                                Err(
-                                                    Response::new_error_without_id(
-                                                        ErrorResponse::new(-32700, "Parse error, invalid JSON", None)
-                                                    )
-                                                )
-                                            }
-                                        }
-                                    },
-                                    ver => {
-                                        log::warn!("Error: Mismatched JSON-RPC version {:?}", ver);
-
-                                        Err(
-                                            Response::new_error_without_id(
-                                                ErrorResponse::new(-32600, "Invalid JSON-RPC version number", None)
-                                            )
-                                        )
-                                    }
-                                }
-                            },
-                            Value::Null => {
-                                log::warn!("Error: \"jsonrpc\" attribute missing");
+        let mut state = serializer.serialize_map(None)?;
 
-                                Err(
-                                    Response::new_error_without_id(
-                                        ErrorResponse::new(-32600, "Missing JSON-RPC version", None)
-                                    )
-                                )
-                            },
-                            _ => {
-                                log::warn!("Error: \"jsonrpc\" attribute is not a string");
+        state.serialize_entry("jsonrpc", "2.0")?;
+
+        if let Some(id) = &self.id {
+            state.serialize_entry("id", id)?;
+        }
+
+        state.serialize_entry("method", &self.method)?;
+
+        if let Some(params) = &self.params {
+            state.serialize_entry("params", params)?;
+        }
+
+        serialize_extensions(&mut state, &self.extensions, REQUEST_MEMBERS)?;
+
+        state.end()
+    }
+}
+
+// Extensions can't override any of the standard members.
+fn serialize_extensions<S>(state: &mut S, extensions: &Map<String,Value>, members: &[&str]) -> Result<(), S::Error>
+where
+    S: SerializeMap,
+{
+    for (key, value) in extensions {
+        if !members.contains(&key.as_str()) {
+            state.serialize_entry(key, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn unknown_members(extensions: &Map<String,Value>) -> String {
+    extensions.keys().map(|k| format!("{:?}", k)).collect::<Vec<_>>().join(", ")
+}
+
+impl Request {
+    /// Rejects any members beyond the standard ones in strict mode, returning
+    /// the error Response for the caller. This is applied when decoding with
+    /// a strictness, and is available separately for transports which need
+    /// to know whether the rejected request was a notification.
+    pub fn check_strictness(self, strictness: Strictness) -> Result<Self, Response> {
+        if strictness == Strictness::Strict && !self.extensions.is_empty() {
+            log::warn!("Error: Unknown JSON-RPC request members {}", unknown_members(&self.extensions));
+
+            return Err(
+                Response::new_error(
+                    self.id.clone().unwrap_or(Id::Null),
+                    ErrorResponse::new(
+                        ErrorCode::InvalidRequest,
+                        "Invalid Request, unknown members",
+                        Some(Value::Array(self.extensions.keys().cloned().map(Value::String).collect()))
+                    )
+                )
+            );
+        }
+
+        Ok(self)
+    }
+
+    fn try_from_value(v: Value, strictness: Strictness) -> Result<Self, Response> {
+        match &v["jsonrpc"] {
+            Value::String(ver) => {
+                match ver.as_str() {
+                    "2.0" => {
+                        match serde_json::from_value::<Request>(v) {
+                            Ok(request) => request.check_strictness(strictness),
+                            Err(err) => {
+                                log::warn!("Error: JSON-RPC deserialization error {:?}", err);
 
                                 Err(
                                     Response::new_error_without_id(
-                                        ErrorResponse::new(-32603, "Non-string JSON-RPC version field", None)
+                                        ErrorResponse::new(ErrorCode::InvalidRequest, "Invalid Request", Some(Value::String(err.to_string())))
                                     )
                                 )
                             }
                         }
                     },
-                    Err(e) => {
-                        log::warn!("Error: Invalid JSON in message ({})", e);
+                    ver => {
+                        log::warn!("Error: Mismatched JSON-RPC version {:?}", ver);
 
                         Err(
                             Response::new_error_without_id(
-                                ErrorResponse::new(-32700, "Parse error, invalid JSON", None)
+                                ErrorResponse::new(ErrorCode::InvalidRequest, "Invalid JSON-RPC version number", None)
                             )
                         )
                     }
                 }
             },
-            Err(e) => {
-                log::warn!("Error: Invalid UTF-8 in message ({})", e);
+            Value::Null => {
+                log::warn!("Error: \"jsonrpc\" attribute missing");
+
+                Err(
+                    Response::new_error_without_id(
+                        ErrorResponse::new(ErrorCode::InvalidRequest, "Missing JSON-RPC version", None)
+                    )
+                )
+            },
+            _ => {
+                log::warn!("Error: \"jsonrpc\" attribute is not a string");
 
                 Err(
                     Response::new_error_without_id(
-                        ErrorResponse::new(-32603, "Internal processing error", None)
+                        ErrorResponse::new(ErrorCode::InvalidRequest, "Non-string JSON-RPC version field", None)
                     )
                 )
             }
@@ -202,12 +644,98 @@ impl TryFrom<&Delivery> for Request {
     }
 }
 
+fn decode_value(codec: &dyn Codec, data: &[u8]) -> Result<Value, Response> {
+    match codec.decode_value(data) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            log::warn!("Error: Invalid {} in message ({})", codec.content_type(), e);
+
+            Err(
+                Response::new_error_without_id(
+                    ErrorResponse::new(ErrorCode::ParseError, "Parse error, invalid payload", None)
+                )
+            )
+        }
+    }
+}
+
+impl Request {
+    /// Decodes a single JSON request. Any failure is returned as the error
+    /// Response that should be sent back to the caller.
+    pub fn decode(data: &[u8]) -> Result<Self, Response> {
+        Self::decode_with(&codec::Json, data)
+    }
+
+    pub fn decode_with(codec: &dyn Codec, data: &[u8]) -> Result<Self, Response> {
+        Self::decode_with_strictness(codec, Strictness::default(), data)
+    }
+
+    pub fn decode_with_strictness(codec: &dyn Codec, strictness: Strictness, data: &[u8]) -> Result<Self, Response> {
+        // Well-formed requests are decoded directly, and only those that
+        // aren't are decoded again as a Value to find out what's wrong.
+        match codec::decode::<Request>(codec, data) {
+            Ok(request) => request.check_strictness(strictness),
+            Err(_) => Self::try_from_value(decode_value(codec, data)?, strictness)
+        }
+    }
+
+    pub fn encode(&self) -> AsyncResult<Vec<u8>> {
+        self.encode_with(&codec::Json)
+    }
+
+    pub fn encode_with(&self, codec: &dyn Codec) -> AsyncResult<Vec<u8>> {
+        codec::encode(codec, self)
+    }
+}
+
+impl Payload<Result<Request,Response>> {
+    /// Decodes either a single JSON request or a batch of them. Problems with
+    /// individual batch entries are reported in place so that the remainder
+    /// of the batch can still be processed.
+    pub fn decode(data: &[u8]) -> Result<Self, Response> {
+        Self::decode_with(&codec::Json, data)
+    }
+
+    pub fn decode_with(codec: &dyn Codec, data: &[u8]) -> Result<Self, Response> {
+        Self::decode_with_strictness(codec, Strictness::default(), data)
+    }
+
+    pub fn decode_with_strictness(codec: &dyn Codec, strictness: Strictness, data: &[u8]) -> Result<Self, Response> {
+        match codec::decode::<Payload<Request>>(codec, data) {
+            Ok(Payload::Single(request)) => {
+                return Ok(Payload::Single(Ok(request.check_strictness(strictness)?)));
+            },
+            Ok(Payload::Batch(batch)) if !batch.is_empty() => {
+                return Ok(Payload::Batch(batch.into_iter().map(|request| request.check_strictness(strictness)).collect()));
+            },
+            _ => { }
+        }
+
+        match decode_value(codec, data)? {
+            Value::Array(items) => {
+                if items.is_empty() {
+                    log::warn!("Error: Empty batch received");
+
+                    return Err(
+                        Response::new_error_without_id(
+                            ErrorResponse::new(ErrorCode::InvalidRequest, "Invalid Request, empty batch", None)
+                        )
+                    );
+                }
+
+                Ok(Payload::Batch(items.into_iter().map(|v| Request::try_from_value(v, strictness)).collect()))
+            },
+            v => Ok(Payload::Single(Ok(Request::try_from_value(v, strictness)?)))
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Request {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        enum Field { Version, Id, Method, Params }
+        enum Field { Version, Id, Method, Params, Other(String) }
 
         impl<'de> Deserialize<'de> for Field {
             fn deserialize<D>(deserializer: D) -> Result<Field, D::Error>
@@ -232,7 +760,7 @@ impl<'de> Deserialize<'de> for Request {
                             "id" => Ok(Field::Id),
                             "method" => Ok(Field::Method),
                             "params" => Ok(Field::Params),
-                            _ => Err(de::Error::unknown_field(value, FIELDS)),
+                            _ => Ok(Field::Other(value.to_string()))
                         }
                     }
                 }
@@ -256,7 +784,7 @@ impl<'de> Deserialize<'de> for Request {
             {
                 let _version : String = seq.next_element()?
                     .ok_or_else(|| de::Error::invalid_length(0, &self))?;
-                let id : String = seq.next_element()?
+                let id : Id = seq.next_element()?
                     .ok_or_else(|| de::Error::invalid_length(1, &self))?;
                 let method : String = seq.next_element()?
                     .ok_or_else(|| de::Error::invalid_length(2, &self))?;
@@ -269,17 +797,28 @@ impl<'de> Deserialize<'de> for Request {
             where
                 V: MapAccess<'de>,
             {
-                let mut id = None;
+                let mut version = false;
+                let mut id : Option<Id> = None;
                 let mut method = None;
                 let mut params = None;
+                let mut extensions = Map::new();
 
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Version => {
-                            // Value is ignored, but must be consumed.
-                            let _version : String = map.next_value()?;
-                        },
-                        Field::Id => {
+                            if version {
+                                return Err(de::Error::duplicate_field("jsonrpc"));
+                            }
+
+                            let value : String = map.next_value()?;
+
+                            if value != "2.0" {
+                                return Err(de::Error::invalid_value(de::Unexpected::Str(&value), &"\"2.0\""));
+                            }
+
+                            version = true;
+                        },
+                        Field::Id => {
                             if id.is_some() {
                                 return Err(de::Error::duplicate_field("id"));
                             }
@@ -299,81 +838,134 @@ impl<'de> Deserialize<'de> for Request {
                             }
 
                             params = Some(map.next_value()?);
+                        },
+                        Field::Other(key) => {
+                            if extensions.contains_key(&key) {
+                                return Err(de::Error::custom(format_args!("duplicate field `{}`", key)));
+                            }
+
+                            extensions.insert(key, map.next_value()?);
                         }
                     }
                 }
 
-                let id : String = id.ok_or_else(|| de::Error::missing_field("id"))?;
+                if !version {
+                    return Err(de::Error::missing_field("jsonrpc"));
+                }
+
                 let method : String = method.ok_or_else(|| de::Error::missing_field("method"))?;
 
-                Ok(Request::new(id, method, params))
+                let mut request = match id {
+                    Some(id) => Request::new(id, method, params),
+                    None => Request::new_notification(method, params)
+                };
+
+                request.extensions = extensions;
+
+                Ok(request)
             }
         }
 
-        const FIELDS: &[&str] = &[ "id", "request", "params" ];
-
-        deserializer.deserialize_struct("Request", FIELDS, RequestVisitor)
+        deserializer.deserialize_struct("Request", REQUEST_MEMBERS, RequestVisitor)
     }
 }
 
 #[derive(Clone,Debug,Eq,PartialEq)]
 pub enum Response {
     Result {
-        id: String,
-        result: Value
+        id: Id,
+        result: Value,
+        extensions: Map<String,Value>,
+        meta: Meta
     },
     Error {
-        id: Option<String>,
-        error: ErrorResponse
+        id: Id,
+        error: ErrorResponse,
+        extensions: Map<String,Value>,
+        meta: Meta
     }
 }
 
 impl Response {
-    pub fn new_result(id: impl ToString, result: Value) -> Self {
+    pub fn new_result(id: impl Into<Id>, result: Value) -> Self {
         Self::Result {
-            id: id.to_string(),
-            result
+            id: id.into(),
+            result,
+            extensions: Map::new(),
+            meta: Meta::new()
         }
     }
 
-    pub fn new_result_serialize(id: impl ToString, result: impl Into<Value>) -> Self {
+    pub fn new_result_serialize(id: impl Into<Id>, result: impl Into<Value>) -> Self {
         Self::Result {
-            id: id.to_string(),
-            result: result.into()
+            id: id.into(),
+            result: result.into(),
+            extensions: Map::new(),
+            meta: Meta::new()
         }
     }
 
-    pub fn new_error(id: impl ToString, error: ErrorResponse) -> Self {
+    pub fn new_error(id: impl Into<Id>, error: ErrorResponse) -> Self {
         Self::Error {
-            id: Some(id.to_string()),
-            error
+            id: id.into(),
+            error,
+            extensions: Map::new(),
+            meta: Meta::new()
         }
     }
 
     pub fn new_error_without_id(error: ErrorResponse) -> Self {
         Self::Error {
-            id: None,
-            error
+            id: Id::Null,
+            error,
+            extensions: Map::new(),
+            meta: Meta::new()
         }
     }
 
     pub fn result_for(request: &Request, result: Value) -> Self {
         Self::Result {
-            id: request.id.clone(), result
+            id: request.id.clone().unwrap_or(Id::Null),
+            result,
+            extensions: Map::new(),
+            meta: Meta::new()
         }
     }
 
-    pub fn error_for(request: &Request, code: i32, message: impl ToString, data: Option<Value>) -> Self {
+    pub fn error_for(request: &Request, code: impl Into<ErrorCode>, message: impl ToString, data: Option<Value>) -> Self {
         Self::Error {
-            id: Some(request.id.clone()),
-            error: ErrorResponse::new(code, message, data)
+            id: request.id.clone().unwrap_or(Id::Null),
+            error: ErrorResponse::new(code, message, data),
+            extensions: Map::new(),
+            meta: Meta::new()
+        }
+    }
+
+    /// Converts an error returned by a Responder into a reply. An
+    /// ErrorResponse is passed through with its code, message and data intact,
+    /// while any other error is reported as an internal error.
+    pub fn error_from(request: &Request, err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        match err.downcast::<ErrorResponse>() {
+            Ok(error) => {
+                Self::Error {
+                    id: request.id.clone().unwrap_or(Id::Null),
+                    error: *error,
+                    extensions: Map::new(),
+                    meta: Meta::new()
+                }
+            },
+            Err(err) => {
+                log::warn!("Error: Internal processing error {:?}", err);
+
+                Self::error_for(request, ErrorCode::InternalError, "Internal processing error", None)
+            }
         }
     }
 
-    pub fn id(&self) -> Option<&String> {
+    pub fn id(&self) -> &Id {
         match self {
-            Self::Result { id, .. } => Some(id),
-            Self::Error { id, .. } => id.as_ref()
+            Self::Result { id, .. } => id,
+            Self::Error { id, .. } => id
         }
     }
 
@@ -393,14 +985,65 @@ impl Response {
             None
         }
     }
+
+    pub fn error(&self) -> Option<&ErrorResponse> {
+        if let Self::Error { error, .. } = &self {
+            Some(error)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Top-level members that aren't part of the JSON-RPC 2.0 specification,
+    /// preserved as with Request::extensions.
+    pub fn extensions(&self) -> &Map<String,Value> {
+        match self {
+            Self::Result { extensions, .. } => extensions,
+            Self::Error { extensions, .. } => extensions
+        }
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Map<String,Value> {
+        match self {
+            Self::Result { extensions, .. } => extensions,
+            Self::Error { extensions, .. } => extensions
+        }
+    }
+
+    pub fn with_extension(mut self, key: impl ToString, value: Value) -> Self {
+        self.extensions_mut().insert(key.to_string(), value);
+
+        self
+    }
+
+    pub fn meta(&self) -> &Meta {
+        match self {
+            Self::Result { meta, .. } => meta,
+            Self::Error { meta, .. } => meta
+        }
+    }
+
+    pub fn meta_mut(&mut self) -> &mut Meta {
+        match self {
+            Self::Result { meta, .. } => meta,
+            Self::Error { meta, .. } => meta
+        }
+    }
+
+    pub fn with_meta(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.meta_mut().insert(key.to_string(), value.to_string());
+
+        self
+    }
 }
 
 impl Display for Response {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            Self::Result { id, result } => write!(f, "{}: {:?}", id, result),
-            Self::Error { id: None, error } => write!(f, "-: {:?}", error),
-            Self::Error { id: Some(id), error } => write!(f, "{}: {:?}", id, error)
+            Self::Result { id, result, .. } => write!(f, "{}: {:?}", id, result),
+            Self::Error { id: Id::Null, error, .. } => write!(f, "-: {:?}", error),
+            Self::Error { id, error, .. } => write!(f, "{}: {:?}", id, error)
         }
     }
 }
@@ -410,7 +1053,7 @@ impl<'de> Deserialize<'de> for Response {
     where
         D: Deserializer<'de>,
     {
-        enum Field { Version, Id, Result, Error }
+        enum Field { Version, Id, Result, Error, Other(String) }
 
         impl<'de> Deserialize<'de> for Field {
             fn deserialize<D>(deserializer: D) -> Result<Field, D::Error>
@@ -435,7 +1078,7 @@ impl<'de> Deserialize<'de> for Response {
                             "id" => Ok(Field::Id),
                             "result" => Ok(Field::Result),
                             "error" => Ok(Field::Error),
-                            _ => Err(de::Error::unknown_field(value, FIELDS)),
+                            _ => Ok(Field::Other(value.to_string()))
                         }
                     }
                 }
@@ -472,15 +1115,26 @@ impl<'de> Deserialize<'de> for Response {
             where
                 V: MapAccess<'de>,
             {
+                let mut version = false;
                 let mut id = None;
                 let mut result = None;
                 let mut error = None;
+                let mut extensions = Map::new();
 
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Version => {
-                            // Value is ignored, but must be consumed.
-                            let _version : String = map.next_value()?;
+                            if version {
+                                return Err(de::Error::duplicate_field("jsonrpc"));
+                            }
+
+                            let value : String = map.next_value()?;
+
+                            if value != "2.0" {
+                                return Err(de::Error::invalid_value(de::Unexpected::Str(&value), &"\"2.0\""));
+                            }
+
+                            version = true;
                         },
                         Field::Id => {
                             if id.is_some() {
@@ -502,17 +1156,28 @@ impl<'de> Deserialize<'de> for Response {
                             }
 
                             error = Some(map.next_value()?);
+                        },
+                        Field::Other(key) => {
+                            if extensions.contains_key(&key) {
+                                return Err(de::Error::custom(format_args!("duplicate field `{}`", key)));
+                            }
+
+                            extensions.insert(key, map.next_value()?);
                         }
                     }
                 }
 
-                let id : String = id.ok_or_else(|| de::Error::missing_field("id"))?;
+                if !version {
+                    return Err(de::Error::missing_field("jsonrpc"));
+                }
+
+                let id : Id = id.ok_or_else(|| de::Error::missing_field("id"))?;
 
                 if let Some(result) = result {
-                    Ok(Response::new_result(id, result))
+                    Ok(Response::Result { id, result, extensions, meta: Meta::new() })
                 }
                 else if let Some(error) = error {
-                    Ok(Response::new_error(id, error))
+                    Ok(Response::Error { id, error, extensions, meta: Meta::new() })
                 }
                 else {
                     Err(de::Error::missing_field("result"))
@@ -520,9 +1185,7 @@ impl<'de> Deserialize<'de> for Response {
             }
         }
 
-        const FIELDS: &[&str] = &[ "jsonrpc", "id", "result", "error" ];
-
-        deserializer.deserialize_struct("Response", FIELDS, ResponseVisitor)
+        deserializer.deserialize_struct("Response", RESPONSE_MEMBERS, ResponseVisitor)
     }
 }
 
@@ -531,18 +1194,22 @@ impl Serialize for Response {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Response", 3)?;
+        let mut state = serializer.serialize_map(None)?;
 
-        state.serialize_field("jsonrpc", "2.0")?;
+        state.serialize_entry("jsonrpc", "2.0")?;
 
         match &self {
-            Self::Result { id, result } => {
-                state.serialize_field("id", id)?;
-                state.serialize_field("result", result)?;
+            Self::Result { id, result, extensions, .. } => {
+                state.serialize_entry("id", id)?;
+                state.serialize_entry("result", result)?;
+
+                serialize_extensions(&mut state, extensions, RESPONSE_MEMBERS)?;
             },
-            Self::Error { id, error } => {
-                state.serialize_field("id", id)?;
-                state.serialize_field("error", error)?;
+            Self::Error { id, error, extensions, .. } => {
+                state.serialize_entry("id", id)?;
+                state.serialize_entry("error", error)?;
+
+                serialize_extensions(&mut state, extensions, RESPONSE_MEMBERS)?;
             }
         }
 
@@ -550,59 +1217,294 @@ impl Serialize for Response {
     }
 }
 
-impl TryFrom<&Delivery> for Response {
-    type Error = &'static str;
-
-    fn try_from(delivery: &Delivery) -> Result<Self, &'static str> {
-        match std::str::from_utf8(&delivery.data) {
-            Ok(s) => {
-                match serde_json::from_str::<Value>(s) {
-                    Ok(v) => {
-                        match &v["jsonrpc"] {
-                            Value::String(ver) => {
-                                match ver.as_str() {
-                                    "2.0" => {
-                                        match serde_json::from_str::<Response>(s) {
-                                            Ok(request) => Ok(request),
-                                            Err(err) => {
-                                                log::warn!("Error: JSON-RPC deserialization error {:?}", err);
-
-                                                Err("Parse error, invalid JSON")
-                                            }
-                                        }
-                                    },
-                                    ver => {
-                                        log::warn!("Error: Mismatched JSON-RPC version {:?}", ver);
-
-                                        Err("Invalid JSON-RPC version number")
-                                    }
-                                }
-                            },
-                            Value::Null => {
-                                log::warn!("Error: \"jsonrpc\" attribute missing");
-
-                                Err("Missing JSON-RPC version")
-                            },
-                            _ => {
-                                log::warn!("Error: \"jsonrpc\" attribute is not a string");
-
-                                Err("Non-string JSON-RPC version field")
+impl Response {
+    // Rejects any members beyond the standard ones in strict mode.
+    fn check_strictness(self, strictness: Strictness) -> Result<Self, &'static str> {
+        if strictness == Strictness::Strict && !self.extensions().is_empty() {
+            log::warn!("Error: Unknown JSON-RPC response members {}", unknown_members(self.extensions()));
+
+            return Err("Invalid Response, unknown members");
+        }
+
+        Ok(self)
+    }
+
+    fn try_from_value(v: Value, strictness: Strictness) -> Result<Self, &'static str> {
+        match &v["jsonrpc"] {
+            Value::String(ver) => {
+                match ver.as_str() {
+                    "2.0" => {
+                        match serde_json::from_value::<Response>(v) {
+                            Ok(response) => response.check_strictness(strictness),
+                            Err(err) => {
+                                log::warn!("Error: JSON-RPC deserialization error {:?}", err);
+
+                                Err("Parse error, invalid JSON")
                             }
                         }
                     },
-                    Err(e) => {
-                        log::warn!("Error: Invalid JSON in message ({})", e);
+                    ver => {
+                        log::warn!("Error: Mismatched JSON-RPC version {:?}", ver);
 
-                        Err("Parse error, invalid JSON")
+                        Err("Invalid JSON-RPC version number")
                     }
                 }
             },
-            Err(e) => {
-                log::warn!("Error: Invalid UTF-8 in message ({})", e);
+            Value::Null => {
+                log::warn!("Error: \"jsonrpc\" attribute missing");
+
+                Err("Missing JSON-RPC version")
+            },
+            _ => {
+                log::warn!("Error: \"jsonrpc\" attribute is not a string");
+
+                Err("Non-string JSON-RPC version field")
+            }
+        }
+    }
+}
+
+fn decode_response_value(codec: &dyn Codec, data: &[u8]) -> Result<Value, &'static str> {
+    match codec.decode_value(data) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            log::warn!("Error: Invalid {} in message ({})", codec.content_type(), e);
+
+            Err("Parse error, invalid payload")
+        }
+    }
+}
+
+impl Response {
+    pub fn decode(data: &[u8]) -> Result<Self, &'static str> {
+        Self::decode_with(&codec::Json, data)
+    }
+
+    pub fn decode_with(codec: &dyn Codec, data: &[u8]) -> Result<Self, &'static str> {
+        Self::decode_with_strictness(codec, Strictness::default(), data)
+    }
+
+    pub fn decode_with_strictness(codec: &dyn Codec, strictness: Strictness, data: &[u8]) -> Result<Self, &'static str> {
+        // As with requests, only those that can't be decoded directly are
+        // decoded again as a Value.
+        match codec::decode::<Response>(codec, data) {
+            Ok(response) => response.check_strictness(strictness),
+            Err(_) => Self::try_from_value(decode_response_value(codec, data)?, strictness)
+        }
+    }
+
+    pub fn encode(&self) -> AsyncResult<Vec<u8>> {
+        self.encode_with(&codec::Json)
+    }
+
+    pub fn encode_with(&self, codec: &dyn Codec) -> AsyncResult<Vec<u8>> {
+        codec::encode(codec, self)
+    }
+}
+
+impl Payload<Response> {
+    pub fn decode(data: &[u8]) -> Result<Self, &'static str> {
+        Self::decode_with(&codec::Json, data)
+    }
+
+    pub fn decode_with(codec: &dyn Codec, data: &[u8]) -> Result<Self, &'static str> {
+        Self::decode_with_strictness(codec, Strictness::default(), data)
+    }
+
+    pub fn decode_with_strictness(codec: &dyn Codec, strictness: Strictness, data: &[u8]) -> Result<Self, &'static str> {
+        match codec::decode::<Payload<Response>>(codec, data) {
+            Ok(Payload::Single(response)) => {
+                return Ok(Payload::Single(response.check_strictness(strictness)?));
+            },
+            Ok(Payload::Batch(batch)) => {
+                return Ok(Payload::Batch(
+                    batch.into_iter().map(|response| {
+                        let id = response.id().clone();
+
+                        response.check_strictness(strictness).unwrap_or_else(|err| {
+                            Response::new_error(id, ErrorResponse::new(ErrorCode::ParseError, err, None))
+                        })
+                    }).collect()
+                ));
+            },
+            Err(_) => { }
+        }
+
+        match decode_response_value(codec, data)? {
+            Value::Array(items) => {
+                // An entry that can't be decoded becomes an error for that
+                // entry alone, tied to its request if its id can be read.
+                Ok(Payload::Batch(
+                    items.into_iter().map(|v| {
+                        let id = v.get("id").and_then(|id| serde_json::from_value(id.clone()).ok()).unwrap_or(Id::Null);
+
+                        Response::try_from_value(v, strictness).unwrap_or_else(|err| {
+                            Response::new_error(id, ErrorResponse::new(ErrorCode::ParseError, err, None))
+                        })
+                    }).collect()
+                ))
+            },
+            v => Ok(Payload::Single(Response::try_from_value(v, strictness)?))
+        }
+    }
+}
+
+/// A JSON-RPC 2.0 batch, transmitted as a top-level JSON array.
+#[derive(Clone,Debug,Eq,PartialEq,Deserialize,Serialize)]
+#[serde(transparent)]
+pub struct Batch<T> {
+    items: Vec<T>
+}
+
+impl<T> Batch<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        Self { items }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.items.push(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.items.iter_mut()
+    }
+}
+
+impl<T> From<Vec<T>> for Batch<T> {
+    fn from(items: Vec<T>) -> Self {
+        Self::new(items)
+    }
+}
+
+impl<T> FromIterator<T> for Batch<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<T> IntoIterator for Batch<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+/// Either a single JSON-RPC object or a batch of them, as found in a message
+/// body.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Payload<T> {
+    Single(T),
+    Batch(Batch<T>)
+}
+
+impl<T> Payload<T> {
+    pub fn is_batch(&self) -> bool {
+        matches!(self, Self::Batch(_))
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        match self {
+            Self::Single(item) => vec![ item ],
+            Self::Batch(batch) => batch.into_items()
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        match self {
+            Self::Single(item) => std::slice::from_ref(item).iter(),
+            Self::Batch(batch) => batch.iter()
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        match self {
+            Self::Single(item) => std::slice::from_mut(item).iter_mut(),
+            Self::Batch(batch) => batch.iter_mut()
+        }
+    }
+}
+
+impl<T> Payload<T> where T : Serialize {
+    pub fn encode(&self) -> AsyncResult<Vec<u8>> {
+        self.encode_with(&codec::Json)
+    }
+
+    pub fn encode_with(&self, codec: &dyn Codec) -> AsyncResult<Vec<u8>> {
+        codec::encode(codec, self)
+    }
+}
+
+impl<T> Serialize for Payload<T> where T : Serialize {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Single(item) => item.serialize(serializer),
+            Self::Batch(batch) => batch.serialize(serializer)
+        }
+    }
+}
+
+// An array is a batch and anything else a single item.
+impl<'de, T> Deserialize<'de> for Payload<T> where T : Deserialize<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PayloadVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for PayloadVisitor<T> where T : Deserialize<'de> {
+            type Value = Payload<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON-RPC object or batch")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Payload<T>, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let mut items = Vec::new();
+
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
 
-                Err("Internal processing error")
+                Ok(Payload::Batch(Batch::new(items)))
+            }
+
+            fn visit_map<V>(self, map: V) -> Result<Payload<T>, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                T::deserialize(de::value::MapAccessDeserializer::new(map)).map(Payload::Single)
             }
         }
+
+        deserializer.deserialize_any(PayloadVisitor(PhantomData))
     }
 }
 
@@ -610,14 +1512,23 @@ impl TryFrom<&Delivery> for Response {
 pub struct ErrorResponse {
     code : i32,
     message : String,
-    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(default,skip_serializing_if="Option::is_none",deserialize_with="deserialize_present")]
     data : Option<Value>
 }
 
+// Distinguishes an explicit `null` from an absent member so that
+// `Some(Value::Null)` survives a round-trip.
+fn deserialize_present<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(Some)
+}
+
 impl ErrorResponse {
-    pub fn new(code: i32, message: impl ToString, data: Option<Value>) -> Self {
+    pub fn new(code: impl Into<ErrorCode>, message: impl ToString, data: Option<Value>) -> Self {
         Self {
-            code,
+            code: code.into().code(),
             message: message.to_string(),
             data
         }
@@ -627,6 +1538,40 @@ impl ErrorResponse {
         self.code
     }
 
+    pub fn error_code(&self) -> ErrorCode {
+        ErrorCode::from(self.code)
+    }
+
+    pub fn is_parse_error(&self) -> bool {
+        self.error_code() == ErrorCode::ParseError
+    }
+
+    pub fn is_invalid_request(&self) -> bool {
+        self.error_code() == ErrorCode::InvalidRequest
+    }
+
+    pub fn is_method_not_found(&self) -> bool {
+        self.error_code() == ErrorCode::MethodNotFound
+    }
+
+    pub fn is_invalid_params(&self) -> bool {
+        self.error_code() == ErrorCode::InvalidParams
+    }
+
+    pub fn is_internal_error(&self) -> bool {
+        self.error_code() == ErrorCode::InternalError
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        self.error_code().is_server_error()
+    }
+
+    /// True for any code in the range reserved by the JSON-RPC 2.0
+    /// specification, as opposed to application-defined codes.
+    pub fn is_reserved(&self) -> bool {
+        self.error_code().is_reserved()
+    }
+
     pub fn message(&self) -> &String {
         &self.message
     }
@@ -644,6 +1589,107 @@ impl fmt::Display for ErrorResponse {
 
 impl std::error::Error for ErrorResponse { }
 
+impl From<ErrorCode> for ErrorResponse {
+    fn from(code: ErrorCode) -> Self {
+        Self::new(code, code.message(), None)
+    }
+}
+
+/// The error codes defined by the JSON-RPC 2.0 specification, including the
+/// -32000 to -32099 range reserved for implementation-defined server errors,
+/// along with -32800 for cancelled requests as used by the Language Server
+/// Protocol.
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+pub enum ErrorCode {
+    ParseError,
+    InvalidRequest,
+    MethodNotFound,
+    InvalidParams,
+    InternalError,
+    RequestCancelled,
+    ServerError(i32),
+    Other(i32)
+}
+
+impl ErrorCode {
+    pub const RESERVED_RANGE : RangeInclusive<i32> = -32768..=-32000;
+    pub const SERVER_ERROR_RANGE : RangeInclusive<i32> = -32099..=-32000;
+
+    /// Returns the server error with the given offset into the
+    /// implementation-defined range, where 0 is -32000 and 99 is -32099.
+    pub fn server_error(offset: u8) -> Option<Self> {
+        let code = -32000 - offset as i32;
+
+        if Self::SERVER_ERROR_RANGE.contains(&code) {
+            Some(Self::ServerError(code))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::ParseError => -32700,
+            Self::InvalidRequest => -32600,
+            Self::MethodNotFound => -32601,
+            Self::InvalidParams => -32602,
+            Self::InternalError => -32603,
+            Self::RequestCancelled => -32800,
+            Self::ServerError(code) => *code,
+            Self::Other(code) => *code
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::ParseError => "Parse error",
+            Self::InvalidRequest => "Invalid Request",
+            Self::MethodNotFound => "Method not found",
+            Self::InvalidParams => "Invalid params",
+            Self::InternalError => "Internal error",
+            Self::RequestCancelled => "Request cancelled",
+            Self::ServerError(_) => "Server error",
+            Self::Other(_) => "Application error"
+        }
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        matches!(self, Self::ServerError(_))
+    }
+
+    pub fn is_reserved(&self) -> bool {
+        Self::RESERVED_RANGE.contains(&self.code())
+    }
+}
+
+impl From<i32> for ErrorCode {
+    fn from(code: i32) -> Self {
+        match code {
+            -32700 => Self::ParseError,
+            -32600 => Self::InvalidRequest,
+            -32601 => Self::MethodNotFound,
+            -32602 => Self::InvalidParams,
+            -32603 => Self::InternalError,
+            -32800 => Self::RequestCancelled,
+            code if Self::SERVER_ERROR_RANGE.contains(&code) => Self::ServerError(code),
+            code => Self::Other(code)
+        }
+    }
+}
+
+impl From<ErrorCode> for i32 {
+    fn from(code: ErrorCode) -> Self {
+        code.code()
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message(), self.code())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -654,11 +1700,24 @@ mod test {
     fn test_request_new() {
         let request = Request::new("0ff0", "echo", None);
 
-        assert_eq!(request.id, "0ff0");
+        assert_eq!(request.id, Some(Id::from("0ff0")));
         assert_eq!(request.method, "echo");
         assert_eq!(request.params, None);
     }
 
+    #[test]
+    fn test_request_eq_ignores_context() {
+        let mut context = RequestContext::new();
+
+        context.delivery_tag = Some(1);
+        context.received = Some(SystemTime::now());
+
+        let request = Request::new("0ff0", "echo", None);
+
+        assert_eq!(request.clone().with_context(context), request);
+        assert_ne!(request, Request::new("0ff1", "echo", None));
+    }
+
     #[test]
     fn test_request_serialize_no_params() {
         let request = Request::new("0ff0", "echo", None);
@@ -685,7 +1744,7 @@ mod test {
             "{\"jsonrpc\":\"2.0\",\"id\":\"0ff0\",\"method\":\"echo\"}"
         ).unwrap();
 
-        assert_eq!(request.id(), "0ff0");
+        assert_eq!(request.id(), Some(&Id::from("0ff0")));
         assert_eq!(request.method(), "echo");
         assert_eq!(request.params(), None);
     }
@@ -696,16 +1755,404 @@ mod test {
             "{\"jsonrpc\":\"2.0\",\"id\":\"0ff0\",\"method\":\"echo\",\"params\":{\"example\":true}}"
         ).unwrap();
 
-        assert_eq!(request.id(), "0ff0");
+        assert_eq!(request.id(), Some(&Id::from("0ff0")));
         assert_eq!(request.method(), "echo");
         assert_eq!(request.params(), Some(&json!({ "example": true })));
     }
 
+    #[test]
+    fn test_request_extensions() {
+        let request : Request = serde_json::from_str(
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\",\"trace\":\"a1b2\"}"
+        ).unwrap();
+
+        assert_eq!(request.extensions().get("trace"), Some(&json!("a1b2")));
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\",\"trace\":\"a1b2\"}"
+        );
+
+        let request = Request::new(1, "echo", None).with_extension("method", json!("other"));
+
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\"}"
+        );
+    }
+
+    #[test]
+    fn test_request_strictness() {
+        let data = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\",\"trace\":\"a1b2\"}";
+
+        assert!(Request::decode_with_strictness(&codec::Json, Strictness::Lenient, data).is_ok());
+
+        let response = Request::decode_with_strictness(&codec::Json, Strictness::Strict, data).unwrap_err();
+
+        assert_eq!(response.id(), &Id::from(1));
+        assert!(matches!(response, Response::Error { ref error, .. } if error.is_invalid_request()));
+
+        let payload = Payload::<Result<Request,Response>>::decode_with_strictness(
+            &codec::Json,
+            Strictness::Strict,
+            b"[{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\"},{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"echo\",\"x\":1}]"
+        ).unwrap();
+
+        let items = payload.into_items();
+
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+
+        assert!(serde_json::from_str::<Request>("{\"jsonrpc\":\"1.0\",\"id\":1,\"method\":\"echo\"}").is_err());
+    }
+
+    #[test]
+    fn test_response_extensions() {
+        let data = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":true,\"trace\":\"a1b2\"}";
+
+        let response = Response::decode(data).unwrap();
+
+        assert_eq!(response.extensions().get("trace"), Some(&json!("a1b2")));
+        assert_eq!(response.encode().unwrap(), data.to_vec());
+
+        assert!(Response::decode_with_strictness(&codec::Json, Strictness::Strict, data).is_err());
+
+        // In a batch, only the entry itself is rejected.
+        let payload = Payload::<Response>::decode_with_strictness(&codec::Json, Strictness::Strict, &[ b"[", &data[..], b"]" ].concat()).unwrap();
+        let items = payload.into_items();
+
+        assert_eq!(items[0].id(), &Id::from(1));
+        assert!(items[0].error().unwrap().is_parse_error());
+    }
+
+    #[test]
+    fn test_request_params_as() {
+        #[derive(Debug,Deserialize,PartialEq)]
+        struct Point {
+            x: i32,
+            y: i32
+        }
+
+        let request = Request::new(1, "plot", Some(json!({ "x": 1, "y": -2 })));
+
+        assert_eq!(request.params_as::<Point>().unwrap(), Point { x: 1, y: -2 });
+        assert_eq!(request.params_as::<(i32,i32)>().unwrap_err().error_code(), ErrorCode::InvalidParams);
+
+        let error = Request::new(1, "plot", Some(json!({ "x": 1 }))).params_as::<Point>().unwrap_err();
+
+        assert!(error.is_invalid_params());
+        assert!(error.data().unwrap().as_str().unwrap().contains("missing field `y`"));
+
+        assert_eq!(Request::new(1, "plot", None).params_as::<Option<Point>>().unwrap(), None);
+
+        let response = Response::error_from(&request, Box::new(error));
+
+        assert!(matches!(response, Response::Error { ref error, .. } if error.is_invalid_params()));
+    }
+
+    #[test]
+    fn test_meta() {
+        let request = Request::new(1, "echo", None).with_meta("trace", "a1b2");
+
+        assert_eq!(request.meta().get("trace").map(String::as_str), Some("a1b2"));
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\"}"
+        );
+
+        let response = Response::result_for(&request, json!(true)).with_meta("elapsed", 5);
+
+        assert_eq!(response.meta().get("elapsed").map(String::as_str), Some("5"));
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":true}"
+        );
+    }
+
+    #[test]
+    fn test_deadline() {
+        let request = Request::new(1, "echo", None);
+
+        assert_eq!(request.remaining(), None);
+        assert!(!request.is_expired());
+
+        let request = request.with_timeout(Duration::from_secs(30));
+
+        assert!(request.remaining().unwrap() > Duration::from_secs(29));
+        assert!(!request.is_expired());
+
+        let request = request.with_deadline(SystemTime::now() - Duration::from_secs(1));
+
+        assert_eq!(request.remaining(), Some(Duration::ZERO));
+        assert!(request.is_expired());
+    }
+
+    #[test]
+    fn test_request_cancel() {
+        let cancel = Request::new_cancel(&Id::from("0ff0"), "amq.gen-a");
+
+        assert!(cancel.is_notification());
+        assert_eq!(cancel.method(), CANCEL_METHOD);
+        assert_eq!(cancel.params(), Some(&json!({ "id": "0ff0", "replyTo": "amq.gen-a" })));
+        assert_eq!(cancel.cancel_target(), Some(Id::from("0ff0")));
+        assert_eq!(cancel.cancel_reply_to(), Some("amq.gen-a"));
+
+        assert_eq!(Request::new_cancel(&Id::from(7), "amq.gen-a").cancel_target(), Some(Id::from(7)));
+        assert_eq!(Request::new_notification(CANCEL_METHOD, None).cancel_target(), None);
+        assert_eq!(Request::new_notification(CANCEL_METHOD, Some(json!({ "id": 1 }))).cancel_reply_to(), None);
+        assert_eq!(Request::new(1, "echo", Some(json!({ "id": 1 }))).cancel_target(), None);
+
+        assert_eq!(ErrorCode::from(-32800), ErrorCode::RequestCancelled);
+        assert_eq!(ErrorCode::RequestCancelled.code(), -32800);
+    }
+
+    #[test]
+    fn test_partial_result() {
+        let notification = Request::new_partial_result(&Id::from("0ff0"), 2, json!([ 1, 2, 3 ]));
+
+        assert!(notification.is_notification());
+        assert_eq!(
+            serde_json::to_value(&notification).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "$/partialResult",
+                "params": { "id": "0ff0", "seq": 2, "value": [ 1, 2, 3 ] }
+            })
+        );
+
+        let decoded : Request = serde_json::from_value(serde_json::to_value(&notification).unwrap()).unwrap();
+
+        assert_eq!(
+            decoded.partial_result(),
+            Some(PartialResult { id: Id::from("0ff0"), seq: 2, value: json!([ 1, 2, 3 ]) })
+        );
+
+        assert_eq!(Request::new_cancel(&Id::from("0ff0"), "amq.gen-a").partial_result(), None);
+        assert!(Response::decode(&notification.encode().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::new().with_percentage(42.0).with_message("Querying");
+        let notification = Request::new_progress(&Id::from(7), &progress);
+
+        assert!(notification.is_notification());
+        assert_eq!(
+            serde_json::to_value(&notification).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "$/progress",
+                "params": { "id": 7, "percentage": 42.0, "message": "Querying" }
+            })
+        );
+
+        let decoded : Request = serde_json::from_value(serde_json::to_value(&notification).unwrap()).unwrap();
+
+        assert_eq!(decoded.progress(), Some((Id::from(7), progress.clone())));
+        assert_eq!(progress.to_string(), "42% Querying");
+
+        assert_eq!(Request::new_notification(PROGRESS_METHOD, Some(json!({ "message": "No id" }))).progress(), None);
+        assert_eq!(Request::new_partial_result(&Id::from(7), 0, json!(1)).progress(), None);
+    }
+
+    #[test]
+    fn test_subscription_event() {
+        let notification = Request::new_subscription_event("a1b2", json!({ "block": 7 }));
+
+        assert!(notification.is_notification());
+        assert_eq!(
+            serde_json::to_value(&notification).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "$/subscription",
+                "params": { "subscription": "a1b2", "result": { "block": 7 } }
+            })
+        );
+
+        assert_eq!(
+            notification.subscription_event(),
+            Some(SubscriptionEvent { subscription: "a1b2".to_string(), result: json!({ "block": 7 }) })
+        );
+
+        assert_eq!(Request::new_notification(SUBSCRIPTION_METHOD, Some(json!({ "result": 1 }))).subscription_event(), None);
+        assert_eq!(Request::new_cancel(&Id::from(1), "amq.gen-a").subscription_event(), None);
+    }
+
+    #[test]
+    fn test_request_numeric_id() {
+        let request : Request = serde_json::from_str(
+            "{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"echo\"}"
+        ).unwrap();
+
+        assert_eq!(request.id(), Some(&Id::from(7)));
+
+        let response = Response::result_for(&request, json!(true));
+
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":7,\"result\":true}"
+        );
+    }
+
+    #[test]
+    fn test_request_null_id() {
+        let request : Request = serde_json::from_str(
+            "{\"jsonrpc\":\"2.0\",\"id\":null,\"method\":\"echo\"}"
+        ).unwrap();
+
+        assert_eq!(request.id(), Some(&Id::Null));
+        assert!(!request.is_notification());
+
+        let response = Response::error_for(&request, -32601, "Method not found", None);
+
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":null,\"error\":{\"code\":-32601,\"message\":\"Method not found\"}}"
+        );
+    }
+
+    #[test]
+    fn test_id_types_distinct() {
+        assert_ne!(Id::from(7), Id::from("7"));
+        assert_eq!(serde_json::to_string(&Id::from("7")).unwrap(), "\"7\"");
+        assert_eq!(serde_json::to_string(&Id::from(7)).unwrap(), "7");
+        assert_eq!(serde_json::to_string(&Id::Null).unwrap(), "null");
+    }
+
+    #[test]
+    fn test_notification_serialize() {
+        let request = Request::new_notification("update", Some(json!([ 1, 2 ])));
+
+        assert!(request.is_notification());
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"method\":\"update\",\"params\":[1,2]}"
+        );
+    }
+
+    #[test]
+    fn test_notification_deserialize() {
+        let request : Request = serde_json::from_str(
+            "{\"jsonrpc\":\"2.0\",\"method\":\"update\",\"params\":[1,2]}"
+        ).unwrap();
+
+        assert!(request.is_notification());
+        assert_eq!(request.id(), None);
+        assert_eq!(request.method(), "update");
+        assert!(!request.reply_to());
+    }
+
+    #[test]
+    fn test_request_decode() {
+        let request = Request::decode(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\"}").unwrap();
+
+        assert_eq!(request, Request::new(1, "echo", None));
+        assert_eq!(Request::decode(&request.encode().unwrap()).unwrap(), request);
+    }
+
+    #[test]
+    fn test_request_decode_errors() {
+        let response = Request::decode(b"{\"jsonrpc\":").unwrap_err();
+
+        assert_eq!(response.id(), &Id::Null);
+        assert!(matches!(response, Response::Error { ref error, .. } if error.is_parse_error()));
+
+        let response = Request::decode(b"{\"jsonrpc\":\"1.0\",\"id\":1,\"method\":\"echo\"}").unwrap_err();
+
+        assert!(matches!(response, Response::Error { ref error, .. } if error.is_invalid_request()));
+
+        let response = Request::decode(b"{\"jsonrpc\":2,\"id\":1,\"method\":\"echo\"}").unwrap_err();
+
+        assert!(matches!(response, Response::Error { ref error, .. } if error.is_invalid_request()));
+    }
+
+    #[test]
+    fn test_request_decode_with_codec() {
+        let request = Request::new("0ff0", "echo", Some(json!([ 1, "two" ])));
+
+        let codecs : Vec<&dyn Codec> = vec![
+            &codec::Json,
+            #[cfg(feature="msgpack")]
+            &codec::MessagePack,
+            #[cfg(feature="cbor")]
+            &codec::Cbor
+        ];
+
+        for codec in codecs {
+            let data = request.encode_with(codec).unwrap();
+
+            assert_eq!(Request::decode_with(codec, &data).unwrap(), request);
+
+            let batch = Payload::Batch(Batch::new(vec![ request.clone(), Request::new_notification("ping", None) ]));
+            let data = batch.encode_with(codec).unwrap();
+
+            assert_eq!(
+                Payload::<Result<Request,Response>>::decode_with(codec, &data).unwrap().into_items(),
+                vec![ Ok(request.clone()), Ok(Request::new_notification("ping", None)) ]
+            );
+
+            let response = Payload::Batch(Batch::new(vec![ Response::new_result(1, json!({ "nested": [ 1.5, null ] })) ]));
+            let data = response.encode_with(codec).unwrap();
+
+            assert_eq!(Payload::<Response>::decode_with(codec, &data).unwrap(), response);
+
+            // Those that don't decode directly still get the specific error.
+            let data = codec::encode(codec, &json!({ "id": 1, "method": "echo" })).unwrap();
+            let response = Request::decode_with(codec, &data).unwrap_err();
+
+            assert_eq!(response.error().unwrap().message(), "Missing JSON-RPC version");
+        }
+    }
+
+    #[test]
+    fn test_payload_decode_batch() {
+        let payload = Payload::<Result<Request,Response>>::decode(
+            b"[{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\"},{\"jsonrpc\":\"2.0\",\"id\":2},{\"jsonrpc\":\"2.0\",\"method\":\"notify\"}]"
+        ).unwrap();
+
+        let items = payload.into_items();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0], Ok(Request::new(1, "echo", None)));
+        assert!(items[1].is_err());
+        assert_eq!(items[2], Ok(Request::new_notification("notify", None)));
+
+        let response = Payload::<Result<Request,Response>>::decode(b"[]").unwrap_err();
+
+        assert!(matches!(response, Response::Error { ref error, .. } if error.is_invalid_request()));
+    }
+
+    #[test]
+    fn test_response_decode() {
+        let response = Response::decode(b"{\"jsonrpc\":\"2.0\",\"id\":\"0ff1\",\"result\":true}").unwrap();
+
+        assert_eq!(response, Response::new_result("0ff1", json!(true)));
+        assert!(Response::decode(b"{\"id\":\"0ff1\",\"result\":true}").is_err());
+
+        let payload = Payload::<Response>::decode(b"[{\"jsonrpc\":\"2.0\",\"id\":\"0ff1\",\"result\":true}]").unwrap();
+
+        assert!(payload.is_batch());
+
+        // Entries that can't be decoded don't take the rest of the batch with
+        // them, and keep their id if it can be read.
+        let payload = Payload::<Response>::decode(
+            b"[{\"jsonrpc\":\"2.0\",\"id\":\"0ff1\",\"result\":true},{\"id\":\"0ff2\",\"result\":true},{\"jsonrpc\":\"2.0\"}]"
+        ).unwrap();
+
+        let items = payload.into_items();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0], Response::new_result("0ff1", json!(true)));
+        assert_eq!(items[1].id(), &Id::from("0ff2"));
+        assert!(items[1].error().unwrap().is_parse_error());
+        assert_eq!(items[2].id(), &Id::Null);
+        assert!(items[2].is_error());
+    }
+
     #[test]
     fn test_response_new() {
         let response = Response::new_result("0ff1", json!("echoed"));
 
-        assert_eq!(response.id(), Some(&"0ff1".to_string()));
+        assert_eq!(response.id(), &Id::from("0ff1"));
         assert_eq!(response.result(), Some(&json!("echoed")));
     }
 
@@ -725,10 +2172,75 @@ mod test {
             "{\"jsonrpc\":\"2.0\",\"id\":\"0ff1\",\"result\":{\"nested\":{\"structure\":true}}}"
         ).unwrap();
 
-        assert_eq!(response.id(), Some(&"0ff1".to_string()));
+        assert_eq!(response.id(), &Id::from("0ff1"));
         assert_eq!(response.result(), Some(&json!({ "nested": {"structure": true } })));
     }
 
+    #[test]
+    fn test_response_deserialize_null_id() {
+        let response : Response = serde_json::from_str(
+            "{\"jsonrpc\":\"2.0\",\"id\":null,\"error\":{\"code\":-32700,\"message\":\"Parse error, invalid JSON\"}}"
+        ).unwrap();
+
+        assert_eq!(response.id(), &Id::Null);
+        assert!(response.is_error());
+    }
+
+    fn assert_round_trip(response: Response) {
+        let json = serde_json::to_string(&response).unwrap();
+        let value = serde_json::from_str::<Value>(&json).unwrap();
+
+        assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), response, "{}", json);
+        assert_eq!(Response::try_from_value(value, Strictness::Strict).unwrap(), response, "{}", json);
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let request = Request::new("0ff2", "echo", None);
+        let numeric = Request::new(42, "echo", None);
+        let null = Request::new(Id::Null, "echo", None);
+        let error = ErrorResponse::new(-32000, "Test message", None);
+        let error_with_data = ErrorResponse::new(-32000, "Test message", Some(json!({ "field": "name" })));
+        let error_with_null = ErrorResponse::new(-32000, "Test message", Some(Value::Null));
+
+        assert_round_trip(Response::new_result("0ff1", json!("echoed")));
+        assert_round_trip(Response::new_result(7, Value::Null));
+        assert_round_trip(Response::new_result_serialize("0ff1", vec![ 1, 2, 3 ]));
+        assert_round_trip(Response::new_error("0ff1", error.clone()));
+        assert_round_trip(Response::new_error(7, error_with_data.clone()));
+        assert_round_trip(Response::new_error_without_id(error.clone()));
+        assert_round_trip(Response::new_error_without_id(error_with_null));
+        assert_round_trip(Response::result_for(&request, json!({ "nested": [ true ] })));
+        assert_round_trip(Response::result_for(&numeric, json!(1.5)));
+        assert_round_trip(Response::result_for(&null, json!(false)));
+        assert_round_trip(Response::error_for(&request, -32601, "Method not found", None));
+        assert_round_trip(Response::error_for(&numeric, -32602, "Invalid params", Some(json!("reason"))));
+        assert_round_trip(Response::error_for(&null, -32603, "Internal processing error", None));
+    }
+
+    #[test]
+    fn test_response_error_from_error_response() {
+        let request = Request::new(3, "create", None);
+        let error = ErrorResponse::new(-32001, "Validation failed", Some(json!({ "name": "required" })));
+
+        let response = Response::error_from(&request, Box::new(error.clone()));
+
+        assert_eq!(response, Response::new_error(3, error));
+    }
+
+    #[test]
+    fn test_response_error_from_other_error() {
+        let request = Request::new(3, "create", None);
+        let error = std::io::Error::other("disk on fire");
+
+        let response = Response::error_from(&request, Box::new(error));
+
+        assert_eq!(
+            response,
+            Response::new_error(3, ErrorResponse::new(ErrorCode::InternalError, "Internal processing error", None))
+        );
+    }
+
     #[test]
     fn test_error_serialize() {
         let error = ErrorResponse::new(-32000, "Test message", None);
@@ -751,13 +2263,95 @@ mod test {
         assert_eq!(error.data, None);
     }
 
+    #[test]
+    fn test_batch_serialize() {
+        let batch = Batch::new(vec![
+            Request::new("0ff0", "echo", None),
+            Request::new("0ff1", "echo", Some(json!([ 1 ])))
+        ]);
+
+        assert_eq!(
+            serde_json::to_string(&batch).unwrap(),
+            "[{\"jsonrpc\":\"2.0\",\"id\":\"0ff0\",\"method\":\"echo\"},{\"jsonrpc\":\"2.0\",\"id\":\"0ff1\",\"method\":\"echo\",\"params\":[1]}]"
+        );
+    }
+
+    #[test]
+    fn test_batch_deserialize() {
+        let batch : Batch<Response> = serde_json::from_str(
+            "[{\"jsonrpc\":\"2.0\",\"id\":\"0ff0\",\"result\":true},{\"jsonrpc\":\"2.0\",\"id\":\"0ff1\",\"error\":{\"code\":-32601,\"message\":\"Method not found\"}}]"
+        ).unwrap();
+
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.items()[0], Response::new_result("0ff0", json!(true)));
+        assert!(batch.items()[1].is_error());
+    }
+
+    #[test]
+    fn test_payload_serialize() {
+        let single = Payload::Single(Response::new_result("0ff0", json!(true)));
+        let batch = Payload::Batch(Batch::new(vec![ Response::new_result("0ff0", json!(true)) ]));
+
+        assert_eq!(
+            serde_json::to_string(&single).unwrap(),
+            "{\"jsonrpc\":\"2.0\",\"id\":\"0ff0\",\"result\":true}"
+        );
+        assert_eq!(
+            serde_json::to_string(&batch).unwrap(),
+            "[{\"jsonrpc\":\"2.0\",\"id\":\"0ff0\",\"result\":true}]"
+        );
+    }
+
+    #[test]
+    fn test_error_code_from_i32() {
+        assert_eq!(ErrorCode::from(-32700), ErrorCode::ParseError);
+        assert_eq!(ErrorCode::from(-32600), ErrorCode::InvalidRequest);
+        assert_eq!(ErrorCode::from(-32601), ErrorCode::MethodNotFound);
+        assert_eq!(ErrorCode::from(-32602), ErrorCode::InvalidParams);
+        assert_eq!(ErrorCode::from(-32603), ErrorCode::InternalError);
+        assert_eq!(ErrorCode::from(-32050), ErrorCode::ServerError(-32050));
+        assert_eq!(ErrorCode::from(-32100), ErrorCode::Other(-32100));
+        assert_eq!(ErrorCode::from(42), ErrorCode::Other(42));
+    }
+
+    #[test]
+    fn test_error_code_server_error() {
+        assert_eq!(ErrorCode::server_error(0), Some(ErrorCode::ServerError(-32000)));
+        assert_eq!(ErrorCode::server_error(99), Some(ErrorCode::ServerError(-32099)));
+        assert_eq!(ErrorCode::server_error(100), None);
+    }
+
+    #[test]
+    fn test_error_response_classify() {
+        let error = ErrorResponse::new(ErrorCode::MethodNotFound, "Method not found", None);
+
+        assert_eq!(error.code(), -32601);
+        assert!(error.is_method_not_found());
+        assert!(error.is_reserved());
+        assert!(!error.is_server_error());
+
+        let error = ErrorResponse::new(-32001, "Overloaded", None);
+
+        assert!(error.is_server_error());
+        assert!(error.is_reserved());
+
+        let error = ErrorResponse::new(1001, "Quota exceeded", None);
+
+        assert_eq!(error.error_code(), ErrorCode::Other(1001));
+        assert!(!error.is_reserved());
+    }
+
     #[test]
     fn shed_outer_array_on_none() {
         let request = Request {
-            id: "3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into(),
+            id: Some("3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into()),
             method: "example".into(),
             params: None,
-            reply_to: false
+            reply_to: false,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
         };
 
         let request = request.shed_single_outer_array();
@@ -768,10 +2362,14 @@ mod test {
     #[test]
     fn shed_outer_array_on_string() {
         let request = Request {
-            id: "3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into(),
+            id: Some("3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into()),
             method: "example".into(),
             params: Some(json!("test")),
-            reply_to: false
+            reply_to: false,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
         };
 
         let request = request.shed_single_outer_array();
@@ -783,10 +2381,14 @@ mod test {
     #[test]
     fn shed_outer_array_on_wrapped_string() {
         let request = Request {
-            id: "3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into(),
+            id: Some("3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into()),
             method: "example".into(),
             params: Some(json!([ "test" ])),
-            reply_to: false
+            reply_to: false,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
         };
 
         let request = request.shed_single_outer_array();
@@ -798,10 +2400,14 @@ mod test {
     #[test]
     fn shed_outer_array_on_array() {
         let request = Request {
-            id: "3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into(),
+            id: Some("3ad8594c-ee4f-4d70-9f9a-abd853458ca4".into()),
             method: "example".into(),
             params: Some(json!([ [ "test" ] ])),
-            reply_to: false
+            reply_to: false,
+            extensions: Map::new(),
+            meta: Meta::new(),
+            deadline: None,
+            context: RequestContext::default()
         };
 
         let request = request.shed_single_outer_array();
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64,Ordering};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+
+use uuid::Uuid;
+
+use crate::AsyncResult;
+use crate::rpc;
+use crate::subscription::Subscriber;
+
+/// Delivers notifications to the caller of a request ahead of its final
+/// response, as implemented by each transport.
+#[async_trait]
+pub trait Notifier : Send + Sync {
+    async fn notify(&self, notification: rpc::Request) -> AsyncResult<()>;
+
+    /// Returns a notifier that outlives the request, for events sent after
+    /// its reply such as those of a subscription, or None if the transport
+    /// doesn't support this.
+    fn detach(&self) -> Option<Arc<dyn Notifier>> {
+        None
+    }
+}
+
+/// Streams partial results and progress for a request back to its caller.
+/// Each call waits until the transport has taken the notification, so a
+/// handler producing results faster than they can be sent is held back, and
+/// as the final response is only sent once the handler returns it always
+/// follows every chunk.
+///
+/// For notifications, or callers not waiting on a reply, everything sent is
+/// discarded. Clones send to the same caller and share the same sequence.
+#[derive(Clone)]
+pub struct Sink {
+    id: Option<rpc::Id>,
+    seq: Arc<AtomicU64>,
+    notifier: Option<Arc<dyn Notifier>>
+}
+
+impl Sink {
+    pub fn new(request: &rpc::Request, notifier: Arc<dyn Notifier>) -> Self {
+        if request.is_notification() || !request.reply_to() {
+            return Self::discard();
+        }
+
+        Self {
+            id: request.id().cloned(),
+            seq: Arc::new(AtomicU64::new(0)),
+            notifier: Some(notifier)
+        }
+    }
+
+    pub fn discard() -> Self {
+        Self {
+            id: None,
+            seq: Arc::new(AtomicU64::new(0)),
+            notifier: None
+        }
+    }
+
+    /// Returns true if anything sent will reach the caller.
+    pub fn is_connected(&self) -> bool {
+        self.notifier.is_some()
+    }
+
+    /// Returns the number of partial results sent so far.
+    pub fn sent(&self) -> u64 {
+        self.seq.load(Ordering::SeqCst)
+    }
+
+    pub async fn partial(&self, value: Value) -> AsyncResult<()> {
+        if let (Some(id), Some(notifier)) = (&self.id, &self.notifier) {
+            let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+
+            notifier.notify(rpc::Request::new_partial_result(id, seq, value)).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn partial_serialize<T>(&self, value: &T) -> AsyncResult<()> where T : Serialize {
+        self.partial(serde_json::to_value(value)?).await
+    }
+
+    /// Starts a subscription for the caller, returning None if they can't be
+    /// sent events. The subscriber's id should be returned as the result.
+    pub fn subscribe(&self) -> Option<Subscriber> {
+        let notifier = self.notifier.as_ref()?.detach()?;
+
+        Some(Subscriber::new(Uuid::new_v4(), notifier))
+    }
+
+    /// Reports progress, which also lets the caller know the request is still
+    /// being worked on so it can wait longer for the result.
+    pub async fn progress(&self, progress: rpc::Progress) -> AsyncResult<()> {
+        if let (Some(id), Some(notifier)) = (&self.id, &self.notifier) {
+            notifier.notify(rpc::Request::new_progress(id, &progress)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        notifications: Mutex<Vec<rpc::Request>>
+    }
+
+    #[async_trait]
+    impl Notifier for Recorder {
+        async fn notify(&self, notification: rpc::Request) -> AsyncResult<()> {
+            self.notifications.lock().unwrap().push(notification);
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial() {
+        let recorder = Arc::new(Recorder::default());
+        let sink = Sink::new(&rpc::Request::new("0ff0", "export", None), recorder.clone());
+
+        sink.partial(json!("first")).await.unwrap();
+        sink.partial_serialize(&[ 2, 3 ]).await.unwrap();
+
+        assert_eq!(sink.sent(), 2);
+        assert_eq!(
+            recorder.notifications.lock().unwrap().iter().map(|n| n.partial_result().unwrap()).collect::<Vec<_>>(),
+            vec![
+                rpc::PartialResult { id: rpc::Id::from("0ff0"), seq: 0, value: json!("first") },
+                rpc::PartialResult { id: rpc::Id::from("0ff0"), seq: 1, value: json!([ 2, 3 ]) }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_progress() {
+        let recorder = Arc::new(Recorder::default());
+        let sink = Sink::new(&rpc::Request::new(1, "report", None), recorder.clone());
+
+        sink.progress(rpc::Progress::new().with_message("Starting")).await.unwrap();
+
+        assert_eq!(sink.sent(), 0);
+        assert_eq!(
+            recorder.notifications.lock().unwrap()[0].progress(),
+            Some((rpc::Id::from(1), rpc::Progress::new().with_message("Starting")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discard() {
+        let recorder = Arc::new(Recorder::default());
+        let sink = Sink::new(&rpc::Request::new_notification("export", None), recorder.clone());
+
+        assert!(!sink.is_connected());
+
+        sink.partial(json!("ignored")).await.unwrap();
+
+        assert!(sink.subscribe().is_none());
+        sink.progress(rpc::Progress::new().with_percentage(50.0)).await.unwrap();
+
+        assert!(recorder.notifications.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc,Mutex};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::AsyncResult;
+use crate::rpc;
+use crate::sink::Notifier;
+
+/// The subscriber can no longer be reached, such as when their reply queue
+/// has gone away, so the subscription should be dropped.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Unreachable(pub String);
+
+impl fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Subscriber {} can no longer be reached", self.0)
+    }
+}
+
+impl std::error::Error for Unreachable { }
+
+/// Sends events to the caller that created a subscription, as obtained from
+/// Sink::subscribe. Events are only delivered once the result of that call,
+/// which should be the subscriber's id, has been sent.
+#[derive(Clone)]
+pub struct Subscriber {
+    id: String,
+    notifier: Arc<dyn Notifier>
+}
+
+impl Subscriber {
+    pub fn new(id: impl ToString, notifier: Arc<dyn Notifier>) -> Self {
+        Self {
+            id: id.to_string(),
+            notifier
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    /// Sends an event, where any error means the subscription should be
+    /// dropped.
+    pub async fn send(&self, result: Value) -> AsyncResult<()> {
+        self.notifier.notify(rpc::Request::new_subscription_event(&self.id, result)).await
+    }
+
+    pub async fn send_serialize<T>(&self, result: &T) -> AsyncResult<()> where T : Serialize {
+        self.send(serde_json::to_value(result)?).await
+    }
+}
+
+impl fmt::Debug for Subscriber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Subscriber").field("id", &self.id).finish()
+    }
+}
+
+/// Tracks active subscribers by id. Any that can't be reached when sent an
+/// event are removed, so subscriptions are cleaned up even if the subscriber
+/// never unsubscribes. This is lazy, as a worker isn't told when a caller
+/// disconnects, so a subscriber that has gone away stays until its next event
+/// or until removed. Clones share the same subscribers.
+#[derive(Clone,Debug,Default)]
+pub struct Subscriptions {
+    subscribers: Arc<Mutex<HashMap<String,Subscriber>>>
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, subscriber: Subscriber) {
+        self.subscribers.lock().unwrap().insert(subscriber.id.clone(), subscriber);
+    }
+
+    /// Removes the subscriber, returning false if there was no such
+    /// subscription.
+    pub fn remove(&self, id: &str) -> bool {
+        self.subscribers.lock().unwrap().remove(id).is_some()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.subscribers.lock().unwrap().contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sends an event to the given subscriber, returning false if there is
+    /// no such subscription or it has been dropped as unreachable.
+    pub async fn send(&self, id: &str, result: Value) -> bool {
+        let subscriber = match self.subscribers.lock().unwrap().get(id) {
+            Some(subscriber) => subscriber.clone(),
+            None => return false
+        };
+
+        self.deliver(&subscriber, result).await
+    }
+
+    /// Sends an event to every subscriber, returning how many it reached.
+    pub async fn broadcast(&self, result: Value) -> usize {
+        let subscribers : Vec<Subscriber> = self.subscribers.lock().unwrap().values().cloned().collect();
+
+        let mut reached = 0;
+
+        for subscriber in subscribers {
+            if self.deliver(&subscriber, result.clone()).await {
+                reached += 1;
+            }
+        }
+
+        reached
+    }
+
+    async fn deliver(&self, subscriber: &Subscriber, result: Value) -> bool {
+        match subscriber.send(result).await {
+            Ok(()) => true,
+            Err(err) => {
+                log::warn!("Error: Dropping subscription {} {}", subscriber.id, err);
+
+                self.remove(&subscriber.id);
+
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        unreachable: bool,
+        notifications: Mutex<Vec<rpc::Request>>
+    }
+
+    #[async_trait]
+    impl Notifier for Recorder {
+        async fn notify(&self, notification: rpc::Request) -> AsyncResult<()> {
+            if self.unreachable {
+                return Err(Box::new(Unreachable("reply".to_string())));
+            }
+
+            self.notifications.lock().unwrap().push(notification);
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions() {
+        let recorder = Arc::new(Recorder::default());
+        let gone = Arc::new(Recorder { unreachable: true, ..Recorder::default() });
+
+        let subscriptions = Subscriptions::new();
+
+        subscriptions.insert(Subscriber::new("a", recorder.clone()));
+        subscriptions.insert(Subscriber::new("b", gone));
+
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(subscriptions.broadcast(json!(1)).await, 1);
+
+        // The unreachable subscriber is dropped along the way.
+        assert!(!subscriptions.contains("b"));
+        assert!(subscriptions.send("a", json!(2)).await);
+        assert!(!subscriptions.send("b", json!(2)).await);
+
+        assert_eq!(
+            recorder.notifications.lock().unwrap().iter().map(|n| n.subscription_event().unwrap().result).collect::<Vec<_>>(),
+            vec![ json!(1), json!(2) ]
+        );
+
+        assert!(subscriptions.remove("a"));
+        assert!(subscriptions.is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+use std::task::{Context,Poll};
+
+use async_trait::async_trait;
+use futures::future::{poll_fn,BoxFuture};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tower_service::Service;
+
+use crate::AsyncResult;
+use crate::openrpc;
+use crate::Responder;
+use crate::rpc;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Adapts a Responder into a tower Service, so layers for timeouts, rate
+/// limits and the like can be put around it. Each call is handled by a clone
+/// of the responder, so calls run concurrently, unless made with shared where
+/// they take turns with a single responder instead.
+///
+/// Partial results and progress aren't carried through tower, so the
+/// responder is given a sink that discards them.
+pub struct ResponderService<R> {
+    responder: Inner<R>
+}
+
+enum Inner<R> {
+    Cloned(R, fn(&R) -> R),
+    Shared(Arc<Mutex<R>>)
+}
+
+impl<R> ResponderService<R> where R : Responder {
+    pub fn new(responder: R) -> Self where R : Clone {
+        Self {
+            responder: Inner::Cloned(responder, R::clone)
+        }
+    }
+
+    /// For responders that can't be cloned, where calls take turns as the
+    /// responder needs exclusive access, and clones of the service share it.
+    pub fn shared(responder: R) -> Self {
+        Self {
+            responder: Inner::Shared(Arc::new(Mutex::new(responder)))
+        }
+    }
+}
+
+impl<R> Clone for ResponderService<R> {
+    fn clone(&self) -> Self {
+        let responder = match &self.responder {
+            Inner::Cloned(responder, clone) => Inner::Cloned(clone(responder), *clone),
+            Inner::Shared(responder) => Inner::Shared(responder.clone())
+        };
+
+        Self { responder }
+    }
+}
+
+impl<R> Service<rpc::Request> for ResponderService<R> where R : Responder {
+    type Response = Value;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, AsyncResult<Value>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: rpc::Request) -> Self::Future {
+        match &self.responder {
+            Inner::Cloned(responder, clone) => {
+                let mut responder = clone(responder);
+
+                Box::pin(async move {
+                    responder.respond(&request).await
+                })
+            },
+            Inner::Shared(responder) => {
+                let responder = responder.clone();
+
+                Box::pin(async move {
+                    responder.lock().await.respond(&request).await
+                })
+            }
+        }
+    }
+}
+
+/// Adapts a tower Service back into a Responder, such as one built up from a
+/// ResponderService with layers, for use by a Worker.
+pub struct ServiceResponder<S> {
+    service: S,
+    document: Option<openrpc::Document>
+}
+
+impl<S> ServiceResponder<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            document: None
+        }
+    }
+
+    /// Answers rpc.discover with the given document, as the service itself
+    /// can't describe its methods.
+    pub fn with_document(mut self, document: openrpc::Document) -> Self {
+        self.document = Some(document);
+
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+#[async_trait]
+impl<S> Responder for ServiceResponder<S> where S : Service<rpc::Request, Response = Value> + Send + Sync + 'static, S::Error : Into<BoxError>, S::Future : Send {
+    fn describe(&self) -> Option<openrpc::Document> {
+        self.document.clone()
+    }
+
+    async fn respond(&mut self, request: &rpc::Request) -> AsyncResult<Value> {
+        poll_fn(|cx| self.service.poll_ready(cx)).await.map_err(Into::into)?;
+
+        self.service.call(request.clone()).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration,Instant};
+
+    use ::tower::{ServiceBuilder,ServiceExt};
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Echo;
+
+    #[async_trait]
+    impl Responder for Echo {
+        async fn respond(&mut self, request: &rpc::Request) -> AsyncResult<Value> {
+            if request.method() == "sleep" {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+
+            Ok(request.params().cloned().unwrap_or(Value::Null))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let mut service = ResponderService::new(Echo);
+
+        assert_eq!(service.call(rpc::Request::new(1, "echo", Some(json!([ 1 ])))).await.unwrap(), json!([ 1 ]));
+
+        let mut responder = ServiceResponder::new(service)
+            .with_document(openrpc::Document::new("echo", "1.0.0"));
+
+        assert_eq!(responder.respond(&rpc::Request::new(2, "echo", Some(json!("a")))).await.unwrap(), json!("a"));
+        assert_eq!(responder.describe().unwrap().info.title, "echo");
+    }
+
+    #[tokio::test]
+    async fn test_layers() {
+        let service = ServiceBuilder::new()
+            .timeout(Duration::from_millis(100))
+            .service(ResponderService::new(Echo));
+
+        let mut responder = ServiceResponder::new(service);
+
+        assert_eq!(responder.respond(&rpc::Request::new(1, "echo", Some(json!(1)))).await.unwrap(), json!(1));
+        assert!(responder.respond(&rpc::Request::new(2, "sleep", None)).await.unwrap_err().is::<::tower::timeout::error::Elapsed>());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_calls() {
+        let service = ServiceBuilder::new()
+            .concurrency_limit(4)
+            .service(ResponderService::new(Echo));
+
+        let start = Instant::now();
+
+        let calls = (0..4).map(|id| service.clone().oneshot(rpc::Request::new(id, "sleep", Some(json!(id)))));
+        let results = futures::future::try_join_all(calls).await.unwrap();
+
+        assert_eq!(results, vec![ json!(0), json!(1), json!(2), json!(3) ]);
+
+        // Clones of the responder handle the calls at once, where a shared one
+        // makes them take turns.
+        assert!(start.elapsed() < Duration::from_millis(600));
+
+        let service = ResponderService::shared(Echo);
+        let start = Instant::now();
+
+        let calls = (0..2).map(|id| service.clone().oneshot(rpc::Request::new(id, "sleep", None)));
+
+        futures::future::try_join_all(calls).await.unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,58 @@
+// Compiles the client generated from tests/fixtures/example.openrpc.json,
+// which codegen's own tests keep up to date, and checks what it sends.
+
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde_json::{json,Value};
+
+use skein_rpc::AsyncResult;
+use skein_rpc::Client;
+
+include!("fixtures/example_client.rs");
+
+// Records each call, answering with a result to suit the method.
+#[derive(Default)]
+struct Recorder {
+    calls: Mutex<Vec<(String, Option<Value>)>>
+}
+
+#[async_trait]
+impl Client for Recorder {
+    async fn rpc_request(&self, method: impl ToString + Send + 'async_trait, params: Option<Value>) -> AsyncResult<Value> {
+        let method = method.to_string();
+
+        self.calls.lock().unwrap().push((method.clone(), params));
+
+        Ok(match method.as_str() {
+            "user.find" => json!({ "name": "Ada" }),
+            "user_find" => json!(true),
+            "add" => json!(3),
+            _ => Value::Null
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_generated_client() {
+    let client = ExampleClient::new(Recorder::default());
+
+    let user = client.user_find(1, Some(2), None).await.unwrap();
+
+    assert_eq!(user.name, "Ada");
+    assert_eq!(user.tags, None);
+
+    assert!(client.user_find_2("Ada".to_string()).await.unwrap());
+    assert_eq!(client.add(1, 2).await.unwrap(), 3);
+    assert_eq!(client.ping().await.unwrap(), Value::Null);
+
+    assert_eq!(
+        client.into_inner().calls.into_inner().unwrap(),
+        vec![
+            ("user.find".to_string(), Some(json!({ "userId": 1, "user_id": 2 }))),
+            ("user_find".to_string(), Some(json!({ "name": "Ada" }))),
+            ("add".to_string(), Some(json!([ 1, 2 ]))),
+            ("ping".to_string(), None)
+        ]
+    );
+}
@@ -0,0 +1,61 @@
+// Generated from the OpenRPC description of example 1.0.0.
+// Changes made here will be overwritten.
+
+#[derive(Clone,Debug,::serde::Deserialize,::serde::Serialize)]
+pub struct UserFindResult {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>
+}
+
+#[derive(Clone,Debug,::serde::Deserialize,::serde::Serialize)]
+pub struct UserFindParams {
+    #[serde(rename = "userId")]
+    pub user_id: i64,
+    #[serde(rename = "user_id")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id_2: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>
+}
+
+#[derive(Clone,Debug,::serde::Deserialize,::serde::Serialize)]
+pub struct UserFindParams2 {
+    pub name: String
+}
+
+/// An example service
+pub struct ExampleClient<C> where C : ::skein_rpc::Client + Sync {
+    client: C
+}
+
+impl<C> ExampleClient<C> where C : ::skein_rpc::Client + Sync {
+    pub fn new(client: C) -> Self {
+        Self { client }
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    pub fn into_inner(self) -> C {
+        self.client
+    }
+
+    /// Finds a user
+    pub async fn user_find(&self, user_id: i64, user_id_2: Option<i64>, r#type: Option<String>) -> ::skein_rpc::AsyncResult<UserFindResult> {
+        ::skein_rpc::Client::rpc_call(&self.client, "user.find", UserFindParams { user_id, user_id_2, r#type }).await
+    }
+
+    pub async fn user_find_2(&self, name: String) -> ::skein_rpc::AsyncResult<bool> {
+        ::skein_rpc::Client::rpc_call(&self.client, "user_find", UserFindParams2 { name }).await
+    }
+
+    pub async fn add(&self, a: i64, b: i64) -> ::skein_rpc::AsyncResult<i64> {
+        ::skein_rpc::Client::rpc_call(&self.client, "add", (a, b)).await
+    }
+
+    pub async fn ping(&self) -> ::skein_rpc::AsyncResult<::serde_json::Value> {
+        ::skein_rpc::Client::rpc_call(&self.client, "ping", ()).await
+    }
+}